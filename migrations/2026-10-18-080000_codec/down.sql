@@ -0,0 +1,3 @@
+ALTER TABLE kvstore DROP COLUMN codec;
+
+ALTER TABLE checkpoints DROP COLUMN codec;
@@ -0,0 +1,4 @@
+-- codec used for encoding data, 0 for bincode
+ALTER TABLE checkpoints ADD COLUMN codec INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE kvstore ADD COLUMN codec INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+ALTER TABLE kvstore DROP COLUMN compression;
+
+ALTER TABLE checkpoints DROP COLUMN compression;
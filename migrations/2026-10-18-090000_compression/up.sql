@@ -0,0 +1,4 @@
+-- compression of encoded data, 0 for none
+ALTER TABLE checkpoints ADD COLUMN compression INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE kvstore ADD COLUMN compression INTEGER NOT NULL DEFAULT 0;
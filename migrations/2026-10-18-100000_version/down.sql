@@ -0,0 +1,3 @@
+ALTER TABLE kvstore DROP COLUMN version;
+
+ALTER TABLE checkpoints DROP COLUMN version;
@@ -0,0 +1,4 @@
+-- schema version of encoded data, 0 for data stored without version
+ALTER TABLE checkpoints ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE kvstore ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
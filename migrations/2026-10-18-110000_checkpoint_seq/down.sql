@@ -0,0 +1,18 @@
+CREATE TABLE checkpoints_old (
+       id INTEGER PRIMARY KEY NOT NULL,
+       key TEXT NOT NULL,
+       data BLOB NOT NULL,
+       ctime TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       mtime TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       codec INTEGER NOT NULL DEFAULT 0,
+       compression INTEGER NOT NULL DEFAULT 0,
+       version INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT INTO checkpoints_old (id, key, data, ctime, mtime, codec, compression, version)
+SELECT id, key, data, ctime, mtime, codec, compression, version
+FROM checkpoints;
+
+DROP TABLE checkpoints;
+
+ALTER TABLE checkpoints_old RENAME TO checkpoints;
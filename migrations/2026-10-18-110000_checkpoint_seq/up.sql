@@ -0,0 +1,25 @@
+-- Order checkpoints by a per-key sequence number instead of create time, and
+-- record timestamps with milliseconds. SQLite cannot alter column defaults,
+-- so the table is rebuilt.
+CREATE TABLE checkpoints_new (
+       id INTEGER PRIMARY KEY NOT NULL,
+       key TEXT NOT NULL,
+       seq INTEGER NOT NULL,
+       data BLOB NOT NULL,
+       ctime TIMESTAMP NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+       mtime TIMESTAMP NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+       codec INTEGER NOT NULL DEFAULT 0,
+       compression INTEGER NOT NULL DEFAULT 0,
+       version INTEGER NOT NULL DEFAULT 0
+);
+
+-- number existing checkpoints in the order used before
+INSERT INTO checkpoints_new (id, key, seq, data, ctime, mtime, codec, compression, version)
+SELECT id, key, ROW_NUMBER() OVER (PARTITION BY key ORDER BY ctime, id), data, ctime, mtime, codec, compression, version
+FROM checkpoints;
+
+DROP TABLE checkpoints;
+
+ALTER TABLE checkpoints_new RENAME TO checkpoints;
+
+CREATE INDEX checkpoints_key_seq ON checkpoints (key, seq);
@@ -0,0 +1,13 @@
+DROP TABLE checkpoint_values;
+
+DROP TABLE checkpoint_tags;
+
+DROP INDEX checkpoints_step;
+
+DROP INDEX checkpoints_label;
+
+ALTER TABLE checkpoints DROP COLUMN note;
+
+ALTER TABLE checkpoints DROP COLUMN step;
+
+ALTER TABLE checkpoints DROP COLUMN label;
@@ -0,0 +1,27 @@
+-- optional metadata of checkpoints for finding them without decoding data
+ALTER TABLE checkpoints ADD COLUMN label TEXT;
+
+ALTER TABLE checkpoints ADD COLUMN step BIGINT;
+
+ALTER TABLE checkpoints ADD COLUMN note TEXT;
+
+CREATE INDEX checkpoints_label ON checkpoints (label);
+
+CREATE INDEX checkpoints_step ON checkpoints (step);
+
+CREATE TABLE checkpoint_tags (
+       checkpoint_id INTEGER NOT NULL,
+       tag TEXT NOT NULL,
+       PRIMARY KEY (checkpoint_id, tag)
+);
+
+CREATE INDEX checkpoint_tags_tag ON checkpoint_tags (tag);
+
+-- named numbers such as energy or force norm. SQLite stores NaN as NULL,
+-- which is read back as NaN.
+CREATE TABLE checkpoint_values (
+       checkpoint_id INTEGER NOT NULL,
+       name TEXT NOT NULL,
+       value DOUBLE,
+       PRIMARY KEY (checkpoint_id, name)
+);
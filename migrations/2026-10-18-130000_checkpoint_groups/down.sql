@@ -0,0 +1,5 @@
+DROP INDEX checkpoints_group_id;
+
+ALTER TABLE checkpoints DROP COLUMN group_id;
+
+DROP TABLE checkpoint_groups;
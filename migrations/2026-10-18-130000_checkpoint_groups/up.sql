@@ -0,0 +1,13 @@
+-- checkpoints of different types committed together
+CREATE TABLE checkpoint_groups (
+       id INTEGER PRIMARY KEY NOT NULL,
+       name TEXT NOT NULL,
+       seq BIGINT NOT NULL,
+       ctime TIMESTAMP NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+);
+
+CREATE INDEX checkpoint_groups_name_seq ON checkpoint_groups (name, seq);
+
+ALTER TABLE checkpoints ADD COLUMN group_id INTEGER;
+
+CREATE INDEX checkpoints_group_id ON checkpoints (group_id);
@@ -0,0 +1,3 @@
+DROP INDEX checkpoints_base_id;
+
+ALTER TABLE checkpoints DROP COLUMN base_id;
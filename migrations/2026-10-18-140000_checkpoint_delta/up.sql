@@ -0,0 +1,4 @@
+-- checkpoint whose data the stored diff applies to, NULL for full data
+ALTER TABLE checkpoints ADD COLUMN base_id INTEGER;
+
+CREATE INDEX checkpoints_base_id ON checkpoints (base_id);
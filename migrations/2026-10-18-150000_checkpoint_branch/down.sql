@@ -0,0 +1,5 @@
+DROP INDEX checkpoints_parent_id;
+
+ALTER TABLE checkpoints DROP COLUMN branch;
+
+ALTER TABLE checkpoints DROP COLUMN parent_id;
@@ -0,0 +1,14 @@
+-- history of checkpoints as a tree: the checkpoint continued from, and the
+-- branch starting from 0 for each key
+ALTER TABLE checkpoints ADD COLUMN parent_id INTEGER;
+
+ALTER TABLE checkpoints ADD COLUMN branch INTEGER NOT NULL DEFAULT 0;
+
+-- existing checkpoints form a linear history
+UPDATE checkpoints SET parent_id = (
+       SELECT c.id FROM checkpoints c
+       WHERE c.key = checkpoints.key AND c.seq < checkpoints.seq
+       ORDER BY c.seq DESC LIMIT 1
+);
+
+CREATE INDEX checkpoints_parent_id ON checkpoints (parent_id);
@@ -0,0 +1,7 @@
+DROP INDEX checkpoint_groups_run_name_seq;
+
+DROP INDEX checkpoints_run_key_seq;
+
+ALTER TABLE checkpoint_groups DROP COLUMN run;
+
+ALTER TABLE checkpoints DROP COLUMN run;
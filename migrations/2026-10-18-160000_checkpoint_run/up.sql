@@ -0,0 +1,9 @@
+-- namespace of checkpoints sharing one file, e.g. different jobs of the same
+-- program. The default run is named by the empty string.
+ALTER TABLE checkpoints ADD COLUMN run TEXT NOT NULL DEFAULT '';
+
+ALTER TABLE checkpoint_groups ADD COLUMN run TEXT NOT NULL DEFAULT '';
+
+CREATE INDEX checkpoints_run_key_seq ON checkpoints (run, key, seq);
+
+CREATE INDEX checkpoint_groups_run_name_seq ON checkpoint_groups (run, name, seq);
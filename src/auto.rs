@@ -0,0 +1,138 @@
+// [[file:../database.note::*auto][auto:1]]
+use std::time::{Duration, Instant};
+
+use crate::checkpoint::{Checkpoint, CheckpointDb, CheckpointMeta};
+use crate::*;
+
+/// Commit checkpoints automatically every some steps, or after some time,
+/// whichever comes first.
+///
+/// # Example
+///
+/// ```no_run
+/// use gosh_database::{AutoCheckpoint, CheckpointDb};
+/// use std::time::Duration;
+///
+/// let chk = CheckpointDb::new("/tmp/test.sqlite");
+/// let mut auto = AutoCheckpoint::new(chk).every(10).interval(Duration::from_secs(600));
+/// let mut x = vec![0.0; 3];
+/// for i in 0..100 {
+///     x[0] = i as f64;
+///     auto.tick(&x).unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AutoCheckpoint {
+    chk: CheckpointDb,
+    every: Option<usize>,
+    interval: Option<Duration>,
+    // ticks since the last commit
+    steps: usize,
+    last_commit: Instant,
+}
+
+impl AutoCheckpoint {
+    /// Commit into `chk`. Nothing is committed automatically until
+    /// [`every`](Self::every) or [`interval`](Self::interval) is set.
+    pub fn new(chk: CheckpointDb) -> Self {
+        Self {
+            chk,
+            every: None,
+            interval: None,
+            steps: 0,
+            last_commit: Instant::now(),
+        }
+    }
+
+    /// Commit every `n` ticks. `n` of 0 is taken as 1.
+    pub fn every(mut self, n: usize) -> Self {
+        self.every = Some(n.max(1));
+        self
+    }
+
+    /// Commit on the first tick after `interval` since the last commit.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Count a step, and commit `state` if it is due. Return true if
+    /// committed.
+    pub fn tick<T: Checkpoint>(&mut self, state: &T) -> Result<bool> {
+        self.tick_with(state, &CheckpointMeta::default())
+    }
+
+    /// Count a step, and commit `state` with metadata `meta` if it is due.
+    /// Return true if committed.
+    pub fn tick_with<T: Checkpoint>(&mut self, state: &T, meta: &CheckpointMeta) -> Result<bool> {
+        self.steps += 1;
+        let by_steps = self.every.is_some_and(|n| self.steps >= n);
+        let by_time = self.interval.is_some_and(|t| self.last_commit.elapsed() >= t);
+        if by_steps || by_time {
+            self.commit_with(state, meta)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Commit `state` now, and restart counting. Return true if committed.
+    pub fn commit<T: Checkpoint>(&mut self, state: &T) -> Result<bool> {
+        self.commit_with(state, &CheckpointMeta::default())
+    }
+
+    /// Commit `state` with metadata `meta` now, and restart counting. Return
+    /// true if committed.
+    pub fn commit_with<T: Checkpoint>(&mut self, state: &T, meta: &CheckpointMeta) -> Result<bool> {
+        let committed = self.chk.commit_with(state, meta)?;
+        self.steps = 0;
+        self.last_commit = Instant::now();
+        Ok(committed)
+    }
+
+    /// Return the underlying checkpoint database.
+    pub fn checkpoint_db(&self) -> &CheckpointDb {
+        &self.chk
+    }
+}
+// auto:1 ends here
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use gut::cli::*;
+
+    #[test]
+    fn test_auto_checkpoint() -> Result<()> {
+        let tdir = tempfile::tempdir()?;
+        let tmpdb = tdir.path().join("test.sqlite");
+        let path = format!("{}", tmpdb.display());
+
+        let chk = CheckpointDb::try_parse_from(["test", "--chk-file", &path, "--chk-every", "3"])?.create();
+        let mut auto = chk.auto();
+        let committed: Vec<_> = (0..10).map(|i| auto.tick(&(i as f64)).unwrap()).collect();
+        assert_eq!(committed.iter().filter(|x| **x).count(), 3);
+        assert!(committed[2] && committed[5] && committed[8]);
+        assert_eq!(chk.load_from_latest::<f64>()?, 8.0);
+
+        // by time
+        let mut auto = AutoCheckpoint::new(chk.clone()).interval(Duration::from_millis(50));
+        assert!(!auto.tick(&1.0)?);
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(auto.tick(&2.0)?);
+        assert!(!auto.tick(&3.0)?);
+
+        // invalid intervals
+        for t in ["-1", "nan", "inf", "x"] {
+            assert!(CheckpointDb::try_parse_from(["test", "--chk-interval", t]).is_err());
+        }
+        assert!(CheckpointDb::try_parse_from(["test", "--chk-every", "0"]).is_err());
+        let chk = CheckpointDb::try_parse_from(["test", "--chk-interval", "0.5"])?;
+        assert_eq!(chk.auto().interval, Some(Duration::from_millis(500)));
+
+        // never without any setting
+        let mut auto = AutoCheckpoint::new(chk.clone());
+        assert!(!(0..100).any(|_| auto.tick(&1.0).unwrap()));
+
+        Ok(())
+    }
+}
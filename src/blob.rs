@@ -0,0 +1,66 @@
+// [[file:../database.note::*blob][blob:1]]
+use crate::codec::CodecId;
+use crate::compression::Compression;
+use crate::error::BoxedError;
+use crate::*;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Encoded data as stored in the `data` column, together with how it was
+/// encoded.
+#[derive(Debug, Clone, Queryable)]
+pub(crate) struct Blob {
+    pub data: Vec<u8>,
+    pub codec: i32,
+    pub compression: i32,
+    pub version: i32,
+}
+
+impl Blob {
+    /// Encode `value` using the codec and compression registered for `T`,
+    /// falling back to the compression set for `db`.
+    pub fn encode<T: Serialize + ?Sized>(value: &T, db: &DbConnection) -> Result<Self, DbError> {
+        let spec = crate::registry::spec_of::<T>();
+        let data = spec.codec.encode(value).map_err(DbError::serialize::<T>)?;
+        let compression = spec.compression.unwrap_or_else(|| db.options().get_compression());
+        Self::pack(data, spec.codec, compression, spec.version).map_err(DbError::serialize::<T>)
+    }
+
+    /// Compress `data` encoded with `codec` for schema `version`.
+    pub fn pack(data: Vec<u8>, codec: CodecId, compression: Compression, version: u32) -> Result<Self, BoxedError> {
+        let blob = match compression.compress(&data)? {
+            Some(compressed) => Self {
+                data: compressed,
+                codec: codec as i32,
+                compression: compression.id(),
+                version: version as i32,
+            },
+            None => Self {
+                data,
+                codec: codec as i32,
+                compression: Compression::None.id(),
+                version: version as i32,
+            },
+        };
+        Ok(blob)
+    }
+
+    /// Return the encoded data after decompression.
+    pub fn unpack(&self) -> Result<Vec<u8>, BoxedError> {
+        Compression::decompress(self.compression, &self.data)
+    }
+
+    /// Decode as `T` using the codec the data was encoded with, upgrading
+    /// data of older versions if needed. `key` is for error report.
+    pub fn decode<T: DeserializeOwned>(&self, key: &str) -> Result<T, DbError> {
+        let codec = CodecId::from_i32(self.codec)
+            .ok_or_else(|| DbError::deserialize::<T>(key, format!("unknown codec id: {}", self.codec)))?;
+        let data = self.unpack().map_err(|e| DbError::deserialize::<T>(key, e))?;
+        let data = crate::registry::spec_of::<T>()
+            .upgrade(codec, self.version as u32, data)
+            .map_err(|e| DbError::deserialize::<T>(key, e))?;
+        codec.decode(&data).map_err(|e| DbError::deserialize::<T>(key, e))
+    }
+}
+// blob:1 ends here
@@ -1,7 +1,692 @@
 use super::*;
 
-use crate::schema::*;
-use crate::*;
+use crate::blob::Blob;
+use crate::codec::CodecId;
+use crate::compression::Compression;
+use crate::retention::RetentionPolicy;
+
+use chrono::NaiveDateTime;
+use diesel::sqlite::Sqlite;
+use std::collections::{BTreeMap, HashMap};
+
+/// Optional metadata of a checkpoint, stored in queryable columns so that
+/// checkpoints can be found without decoding their data.
+///
+/// # Example
+///
+/// ```no_run
+/// use gosh_database::prelude::*;
+/// use gosh_database::{CheckpointMeta, DbConnection};
+///
+/// let db = DbConnection::connect("/tmp/test.sqlite").unwrap();
+/// let x = vec![1.0, 2.0];
+/// let meta = CheckpointMeta::new().label("relaxed").step(100).tag("converged").value("energy", -1.2);
+/// x.commit_checkpoint_with(&db, &meta).unwrap();
+/// let x = Vec::<f64>::from_checkpoint_tagged(&db, "converged").unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointMeta {
+    /// A name for the checkpoint, not necessarily unique.
+    pub label: Option<String>,
+    /// Step number of the calculation, e.g. the optimization step.
+    pub step: Option<i64>,
+    /// User defined tags.
+    pub tags: Vec<String>,
+    /// Free text note.
+    pub note: Option<String>,
+    /// Named numbers, e.g. energy or force norm.
+    pub values: BTreeMap<String, f64>,
+}
+
+impl CheckpointMeta {
+    /// Empty metadata.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set label.
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Set step number.
+    pub fn step(mut self, step: i64) -> Self {
+        self.step = Some(step);
+        self
+    }
+
+    /// Add a tag.
+    pub fn tag(mut self, tag: &str) -> Self {
+        if !self.tags.iter().any(|x| x == tag) {
+            self.tags.push(tag.into());
+        }
+        self
+    }
+
+    /// Set note.
+    pub fn note(mut self, note: &str) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+
+    /// Set a named number.
+    pub fn value(mut self, name: &str, value: f64) -> Self {
+        self.values.insert(name.into(), value);
+        self
+    }
+}
+
+/// Summary of a stored checkpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointInfo {
+    /// Index of the checkpoint among all with the same key (0-base), as for
+    /// [`Checkpoint::from_checkpoint_n`].
+    pub slot: usize,
+    /// Row id in database.
+    pub id: i32,
+    /// Sequence number of the checkpoint, increasing with each commit for
+    /// the same key.
+    pub seq: i64,
+    /// The name of checkpoint, see [`Checkpoint::checkpoint_name`].
+    pub key: String,
+    /// The run of checkpoint, see [`DbConnection::with_run`].
+    pub run: String,
+    /// Create time in UTC.
+    pub ctime: NaiveDateTime,
+    /// Last modified time in UTC.
+    pub mtime: NaiveDateTime,
+    /// Size of stored data in bytes.
+    pub size: usize,
+    /// Codec the data was encoded with. None if unknown to this library.
+    pub codec: Option<CodecId>,
+    /// Compression of the stored data. None if unknown to this library.
+    pub compression: Option<Compression>,
+    /// Schema version of the stored data.
+    pub version: u32,
+    /// Metadata committed with the checkpoint.
+    pub meta: CheckpointMeta,
+    /// Id of the [`CheckpointGroup`](crate::CheckpointGroup) committed with.
+    pub group_id: Option<i32>,
+    /// Id of the checkpoint the stored data is a diff against, None for full
+    /// data. See [`Register::delta`](crate::Register::delta).
+    pub base_id: Option<i32>,
+    /// Id of the checkpoint this one continues from, None for the first.
+    pub parent_id: Option<i32>,
+    /// Branch of history, 0 for the main one. A new branch starts when
+    /// committing after restoring from a checkpoint not the latest in its
+    /// branch.
+    pub branch: i32,
+}
+
+/// Summary of a branch of checkpoint history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BranchInfo {
+    /// Branch number, 0 for the main one.
+    pub branch: i32,
+    /// Id of the checkpoint the branch forked from. None if the branch
+    /// starts from scratch, or the checkpoint was deleted.
+    pub fork_id: Option<i32>,
+    /// Number of checkpoints in the branch.
+    pub len: usize,
+    /// The latest checkpoint in the branch.
+    pub tip: CheckpointInfo,
+}
+
+type InfoRow = (
+    i32,
+    i64,
+    String,
+    NaiveDateTime,
+    NaiveDateTime,
+    i32,
+    i32,
+    i32,
+    i32,
+    Option<String>,
+    Option<i64>,
+    Option<String>,
+    Option<i32>,
+    Option<i32>,
+    Option<i32>,
+    i32,
+);
+
+/// Return the query of checkpoints in `ckpt_run` with any of `keys`.
+pub(crate) fn slot_query<'a>(
+    ckpt_run: &'a str,
+    keys: &'a [String],
+) -> crate::schema::checkpoints::BoxedQuery<'a, Sqlite> {
+    use crate::schema::checkpoints::dsl::*;
+
+    checkpoints
+        .filter(run.eq(ckpt_run))
+        .filter(key.eq_any(keys))
+        .into_boxed()
+}
+
+/// Return the number of checkpoints in `ckpt_run` with any of `keys`.
+pub(crate) fn count_checkpoints(conn: &SqliteConnection, ckpt_run: &str, keys: &[String]) -> Result<usize, DbError> {
+    let n: i64 = slot_query(ckpt_run, keys).count().get_result(conn)?;
+    Ok(n as usize)
+}
+
+/// Load summaries of checkpoints in `ckpt_run` with any of `keys`, ordered by
+/// slot. The first key is the current name, and others are aliases, which are
+/// placed before the current name.
+pub(crate) fn load_checkpoint_infos(
+    conn: &SqliteConnection,
+    ckpt_run: &str,
+    keys: &[String],
+) -> Result<Vec<CheckpointInfo>, DbError> {
+    load_checkpoint_info_range(conn, ckpt_run, keys, 0, None)
+}
+
+/// Load summaries of checkpoints from slot `start`, at most `limit` of them
+/// if set. See [`load_checkpoint_infos`].
+pub(crate) fn load_checkpoint_info_range(
+    conn: &SqliteConnection,
+    ckpt_run: &str,
+    keys: &[String],
+    start: usize,
+    limit: Option<usize>,
+) -> Result<Vec<CheckpointInfo>, DbError> {
+    use crate::schema::checkpoints::dsl::*;
+    use diesel::dsl::sql;
+    use diesel::sql_types::Integer;
+
+    // negative limit for no limit in SQLite
+    let slots = || {
+        slot_query(ckpt_run, keys)
+            .order((key.eq(&keys[0]).asc(), seq.asc()))
+            .offset(start as i64)
+            .limit(limit.map_or(-1, |n| n as i64))
+    };
+    let rows: Vec<InfoRow> = slots()
+        .select((
+            id,
+            seq,
+            key,
+            ctime,
+            mtime,
+            sql::<Integer>("length(data)"),
+            codec,
+            compression,
+            version,
+            label,
+            step,
+            note,
+            group_id,
+            base_id,
+            parent_id,
+            branch,
+        ))
+        .load(conn)?;
+
+    // metadata only for the loaded ones
+    let ids = || slots().select(id);
+    let mut tags: HashMap<i32, Vec<String>> = HashMap::new();
+    {
+        use crate::schema::checkpoint_tags::dsl::*;
+        let rows: Vec<(i32, String)> = checkpoint_tags
+            .filter(checkpoint_id.eq_any(ids()))
+            .order((checkpoint_id, tag))
+            .load(conn)?;
+        for (ckpt_id, t) in rows {
+            tags.entry(ckpt_id).or_default().push(t);
+        }
+    }
+    let mut values: HashMap<i32, BTreeMap<String, f64>> = HashMap::new();
+    {
+        use crate::schema::checkpoint_values::dsl::*;
+        let rows: Vec<(i32, String, Option<f64>)> = checkpoint_values
+            .filter(checkpoint_id.eq_any(ids()))
+            .select((checkpoint_id, name, value))
+            .load(conn)?;
+        for (ckpt_id, k, v) in rows {
+            // NaN is stored as NULL
+            values.entry(ckpt_id).or_default().insert(k, v.unwrap_or(f64::NAN));
+        }
+    }
+
+    let infos = rows
+        .into_iter()
+        .enumerate()
+        .map(|(i, row)| CheckpointInfo {
+            slot: start + i,
+            id: row.0,
+            seq: row.1,
+            key: row.2,
+            run: ckpt_run.into(),
+            ctime: row.3,
+            mtime: row.4,
+            size: row.5 as usize,
+            codec: CodecId::from_i32(row.6),
+            compression: Compression::from_id(row.7),
+            version: row.8 as u32,
+            meta: CheckpointMeta {
+                label: row.9,
+                step: row.10,
+                note: row.11,
+                tags: tags.remove(&row.0).unwrap_or_default(),
+                values: values.remove(&row.0).unwrap_or_default(),
+            },
+            group_id: row.12,
+            base_id: row.13,
+            parent_id: row.14,
+            branch: row.15,
+        })
+        .collect();
+    Ok(infos)
+}
+
+/// Load the summary of checkpoint in slot `n`. Negative `n` counts from the
+/// end. See [`load_checkpoint_infos`].
+pub(crate) fn load_checkpoint_info_n(
+    conn: &SqliteConnection,
+    ckpt_run: &str,
+    keys: &[String],
+    n: i32,
+) -> Result<CheckpointInfo, DbError> {
+    let nckpts = count_checkpoints(conn, ckpt_run, keys)?;
+    let out_of_range = || DbError::SlotOutOfRange {
+        key: keys[0].clone(),
+        slot: n,
+        len: nckpts,
+    };
+    // Allow negative index into the list.
+    let k = if n < 0 { nckpts as i64 + n as i64 } else { n as i64 };
+    if k < 0 || k >= nckpts as i64 {
+        return Err(out_of_range());
+    }
+    load_checkpoint_info_range(conn, ckpt_run, keys, k as usize, Some(1))?
+        .pop()
+        .ok_or_else(out_of_range)
+}
+
+/// Load the summary of checkpoint `ckpt_id`, which is one of checkpoints in
+/// `ckpt_run` with any of `keys`. See [`load_checkpoint_infos`].
+pub(crate) fn load_checkpoint_info(
+    conn: &SqliteConnection,
+    ckpt_run: &str,
+    keys: &[String],
+    ckpt_id: i32,
+) -> Result<CheckpointInfo, DbError> {
+    use crate::schema::checkpoints::dsl::*;
+
+    let (ckpt_key, ckpt_seq): (String, i64) = checkpoints.filter(id.eq(ckpt_id)).select((key, seq)).first(conn)?;
+    // the number of checkpoints placed before
+    let before = if ckpt_key == keys[0] {
+        slot_query(ckpt_run, keys).filter(key.ne(&keys[0]).or(seq.lt(ckpt_seq)))
+    } else {
+        slot_query(ckpt_run, keys)
+            .filter(key.ne(&keys[0]))
+            .filter(seq.lt(ckpt_seq))
+    };
+    let slot: i64 = before.count().get_result(conn)?;
+    let info = load_checkpoint_info_range(conn, ckpt_run, keys, slot as usize, Some(1))?
+        .pop()
+        .expect("checkpoint info");
+    Ok(info)
+}
+
+/// Return the id of the latest checkpoint in `query`, which is
+/// [`slot_query`] with more filters.
+pub(crate) fn latest_checkpoint_id(
+    conn: &SqliteConnection,
+    query: crate::schema::checkpoints::BoxedQuery<Sqlite>,
+    keys: &[String],
+) -> Result<Option<i32>, DbError> {
+    use crate::schema::checkpoints::dsl::*;
+
+    let ckpt_id = query
+        .order((key.eq(&keys[0]).desc(), seq.desc()))
+        .select(id)
+        .first(conn)
+        .optional()?;
+    Ok(ckpt_id)
+}
+
+/// Return the latest checkpoint whose metadata satisfy `pred`. `query` is
+/// for error report. Checkpoints are scanned back from the latest in pages.
+fn find_latest(
+    conn: &SqliteConnection,
+    ckpt_run: &str,
+    keys: &[String],
+    query: &str,
+    pred: impl Fn(&CheckpointMeta) -> bool,
+) -> Result<CheckpointInfo, DbError> {
+    const PAGE_SIZE: usize = 100;
+
+    let mut end = count_checkpoints(conn, ckpt_run, keys)?;
+    while end > 0 {
+        let start = end.saturating_sub(PAGE_SIZE);
+        let infos = load_checkpoint_info_range(conn, ckpt_run, keys, start, Some(end - start))?;
+        if let Some(x) = infos.into_iter().rev().find(|x| pred(&x.meta)) {
+            return Ok(x);
+        }
+        end = start;
+    }
+    Err(DbError::CheckpointNotFound {
+        key: keys[0].clone(),
+        query: query.into(),
+    })
+}
+
+/// Save tags and named values in `meta` for checkpoint `ckpt_id`.
+pub(crate) fn save_checkpoint_meta(
+    conn: &SqliteConnection,
+    ckpt_id: i32,
+    meta: &CheckpointMeta,
+) -> Result<(), DbError> {
+    {
+        use crate::schema::checkpoint_tags::dsl::*;
+        let rows: Vec<_> = meta
+            .tags
+            .iter()
+            .map(|t| (checkpoint_id.eq(ckpt_id), tag.eq(t)))
+            .collect();
+        diesel::insert_or_ignore_into(checkpoint_tags)
+            .values(&rows)
+            .execute(conn)?;
+    }
+    {
+        use crate::schema::checkpoint_values::dsl::*;
+        let rows: Vec<_> = meta
+            .values
+            .iter()
+            .map(|(k, v)| (checkpoint_id.eq(ckpt_id), name.eq(k), value.eq(v)))
+            .collect();
+        diesel::insert_into(checkpoint_values).values(&rows).execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Delete checkpoints `ids` together with their metadata. Members of a
+/// checkpoint group are restored together, so deleting any of them deletes
+/// the whole group. Checkpoints stored as diffs against deleted ones are
+/// rewritten with full data. Should be called inside a transaction on `db`.
+/// Return the number of deleted checkpoints.
+pub(crate) fn delete_checkpoints(db: &DbConnection, ids: &[i32]) -> Result<usize, DbError> {
+    let conn = &*db.writer();
+    let mut ids = ids.to_vec();
+    let groups: Vec<i32> = {
+        use crate::schema::checkpoints::dsl::*;
+        let groups: Vec<Option<i32>> = checkpoints
+            .filter(id.eq_any(&ids))
+            .filter(group_id.is_not_null())
+            .select(group_id)
+            .distinct()
+            .load(conn)?;
+        let groups = groups.into_iter().flatten().collect_vec();
+        if !groups.is_empty() {
+            ids.extend(
+                checkpoints
+                    .filter(group_id.eq_any(&groups))
+                    .select(id)
+                    .load::<i32>(conn)?,
+            );
+            ids.sort_unstable();
+            ids.dedup();
+        }
+        groups
+    };
+    let ids = &ids[..];
+    {
+        use crate::schema::checkpoints::dsl::*;
+        let dependents: Vec<(i32, i32)> = checkpoints
+            .filter(base_id.eq_any(ids))
+            .filter(id.ne_all(ids))
+            .select((id, compression))
+            .load(conn)?;
+        for (ckpt_id, ckpt_compression) in dependents {
+            let raw = load_checkpoint_blob(conn, ckpt_id)?.data;
+            let c = Compression::from_id(ckpt_compression).unwrap_or_default();
+            let (stored, c) = match c.compress(&raw).map_err(DbError::serialize::<[u8]>)? {
+                Some(compressed) => (compressed, c),
+                None => (raw, Compression::None),
+            };
+            diesel::update(checkpoints.filter(id.eq(ckpt_id)))
+                .set((
+                    data.eq(stored),
+                    compression.eq(c.id()),
+                    base_id.eq(None::<i32>),
+                    mtime.eq(chrono::Utc::now().naive_utc()),
+                ))
+                .execute(conn)?;
+        }
+    }
+    {
+        // keep ancestry of remaining checkpoints by linking children of
+        // deleted ones to their parents.
+        use crate::schema::checkpoints::dsl::*;
+        for &ckpt_id in ids {
+            let ckpt_parent: Option<i32> = checkpoints.filter(id.eq(ckpt_id)).select(parent_id).first(conn)?;
+            diesel::update(checkpoints.filter(parent_id.eq(ckpt_id)))
+                .set(parent_id.eq(ckpt_parent))
+                .execute(conn)?;
+        }
+    }
+    {
+        use crate::schema::checkpoint_tags::dsl::*;
+        diesel::delete(checkpoint_tags.filter(checkpoint_id.eq_any(ids))).execute(conn)?;
+    }
+    {
+        use crate::schema::checkpoint_values::dsl::*;
+        diesel::delete(checkpoint_values.filter(checkpoint_id.eq_any(ids))).execute(conn)?;
+    }
+    let n = {
+        use crate::schema::checkpoints::dsl::*;
+        diesel::delete(checkpoints.filter(id.eq_any(ids))).execute(conn)?
+    };
+    {
+        use crate::schema::checkpoint_groups::dsl::*;
+        diesel::delete(checkpoint_groups.filter(id.eq_any(&groups))).execute(conn)?;
+    }
+    db.drop_checkpoint_heads(ids);
+    Ok(n)
+}
+
+/// Load `T` from checkpoint in slot `n`, together with its summary.
+fn load_checkpoint_n<T: Checkpoint>(db: &DbConnection, n: i32) -> Result<(T, CheckpointInfo), DbError> {
+    let conn = db.reader()?;
+    let ckpt_key = T::checkpoint_name();
+    let ckpt = load_checkpoint_info_n(&conn, db.run(), &crate::registry::names_of::<T>(), n)?;
+    let x = load_checkpoint(&conn, ckpt.id, &format!("{}/{}", ckpt_key, n))?;
+    Ok((x, ckpt))
+}
+
+/// Load `T` from the latest checkpoint created at or before `time` in UTC,
+/// together with its summary.
+fn load_checkpoint_at<T: Checkpoint>(db: &DbConnection, time: NaiveDateTime) -> Result<(T, CheckpointInfo), DbError> {
+    use crate::schema::checkpoints::dsl::*;
+
+    let conn = db.reader()?;
+    let ckpt_key = T::checkpoint_name();
+    let keys = crate::registry::names_of::<T>();
+    let query = format!("create time <= {} UTC", time);
+    let found: Option<i32> = slot_query(db.run(), &keys)
+        .filter(ctime.le(time))
+        .order((ctime.desc(), key.eq(&keys[0]).desc(), seq.desc()))
+        .select(id)
+        .first(&*conn)
+        .optional()?;
+    let ckpt_id = match found {
+        Some(ckpt_id) => ckpt_id,
+        None => {
+            let first = load_checkpoint_info_range(&conn, db.run(), &keys, 0, Some(1))?;
+            let query = match first.first() {
+                Some(first) => format!("{} (the first was created at {} UTC)", query, first.ctime),
+                None => query,
+            };
+            return Err(DbError::CheckpointNotFound { key: ckpt_key, query });
+        }
+    };
+    let ckpt = load_checkpoint_info(&conn, db.run(), &keys, ckpt_id)?;
+    let x = load_checkpoint(&conn, ckpt.id, &format!("{}/{}", ckpt_key, query))?;
+    Ok((x, ckpt))
+}
+
+/// Load `T` from checkpoint `ckpt_id`. `desc` is for error report.
+pub(crate) fn load_checkpoint<T: Checkpoint>(conn: &SqliteConnection, ckpt_id: i32, desc: &str) -> Result<T, DbError> {
+    load_checkpoint_blob(conn, ckpt_id)?.decode(desc)
+}
+
+/// Insert `value` as a new checkpoint with metadata `meta` into checkpoint
+/// group `group`. Return the id of inserted checkpoint.
+pub(crate) fn insert_checkpoint<T: Checkpoint>(
+    db: &DbConnection,
+    value: &T,
+    meta: &CheckpointMeta,
+    group: Option<i32>,
+) -> Result<i32, DbError> {
+    use crate::schema::checkpoints::dsl::*;
+
+    let ckpt_key = T::checkpoint_name();
+    let spec = crate::registry::spec_of::<T>();
+    let raw = spec.codec.encode(value).map_err(DbError::serialize::<T>)?;
+    let c = spec.compression.unwrap_or_else(|| db.options().get_compression());
+    let pack = |raw| Blob::pack(raw, spec.codec, c, spec.version).map_err(DbError::serialize::<T>);
+
+    db.transaction(|tx| {
+        let conn = tx.writer();
+        let new_seq = checkpoints
+            .filter(run.eq(tx.run()))
+            .filter(key.eq(&ckpt_key))
+            .select(diesel::dsl::max(seq))
+            .first::<Option<i64>>(&*conn)?
+            .unwrap_or(0)
+            + 1;
+
+        // continue from the checkpoint restored from or committed last in
+        // this session, or else from the latest one.
+        let head = match tx.checkpoint_head(&ckpt_key) {
+            Some(head_id) => checkpoints
+                .filter(id.eq(head_id))
+                .filter(run.eq(tx.run()))
+                .filter(key.eq(&ckpt_key))
+                .select((id, seq, branch))
+                .first(&*conn)
+                .optional()?,
+            None => None,
+        };
+        let parent: Option<(i32, i64, i32)> = match head {
+            Some(head) => Some(head),
+            None => checkpoints
+                .filter(run.eq(tx.run()))
+                .filter(key.eq(&ckpt_key))
+                .select((id, seq, branch))
+                .order(seq.desc())
+                .first(&*conn)
+                .optional()?,
+        };
+        // start a new branch if the parent has been continued
+        let new_branch = match parent {
+            Some((parent_ckpt, parent_seq, parent_branch)) => {
+                let continued: i64 = checkpoints
+                    .filter(run.eq(tx.run()))
+                    .filter(key.eq(&ckpt_key))
+                    .filter(branch.eq(parent_branch))
+                    .filter(seq.gt(parent_seq))
+                    .count()
+                    .get_result(&*conn)?;
+                let forked: i64 = checkpoints
+                    .filter(parent_id.eq(parent_ckpt))
+                    .count()
+                    .get_result(&*conn)?;
+                if continued > 0 || forked > 0 {
+                    let last: Option<i32> = checkpoints
+                        .filter(run.eq(tx.run()))
+                        .filter(key.eq(&ckpt_key))
+                        .select(diesel::dsl::max(branch))
+                        .first(&*conn)?;
+                    last.unwrap_or(0) + 1
+                } else {
+                    parent_branch
+                }
+            }
+            None => 0,
+        };
+        let parent = parent.map(|(parent_ckpt, _, _)| parent_ckpt);
+
+        // store diff against the parent checkpoint, except for keyframes
+        let mut delta = None;
+        if let (Some(k), Some(parent_ckpt)) = (spec.keyframe_every, parent) {
+            if (new_seq - 1) % k as i64 != 0 {
+                let base = load_checkpoint_blob(&conn, parent_ckpt)?;
+                let base = base.unpack().map_err(|e| DbError::deserialize::<[u8]>(&ckpt_key, e))?;
+                let diff = crate::delta::diff(&base, &raw);
+                if diff.len() < raw.len() {
+                    delta = Some((pack(diff)?, parent_ckpt));
+                }
+            }
+        }
+        let (blob, base) = match delta {
+            Some((blob, last_id)) => (blob, Some(last_id)),
+            None => (pack(raw)?, None),
+        };
+
+        let now = chrono::Utc::now().naive_utc();
+        let row = (
+            key.eq(&ckpt_key),
+            run.eq(tx.run()),
+            seq.eq(new_seq),
+            base_id.eq(base),
+            data.eq(blob.data),
+            codec.eq(blob.codec),
+            compression.eq(blob.compression),
+            version.eq(blob.version),
+            ctime.eq(now),
+            mtime.eq(now),
+            label.eq(&meta.label),
+            step.eq(meta.step),
+            note.eq(&meta.note),
+            group_id.eq(group),
+            parent_id.eq(parent),
+            branch.eq(new_branch),
+        );
+        diesel::insert_into(checkpoints).values(&row).execute(&*conn)?;
+        let ckpt_id: i32 = checkpoints.select(id).order(id.desc()).first(&*conn)?;
+        save_checkpoint_meta(&conn, ckpt_id, meta)?;
+        tx.set_checkpoint_head(&ckpt_key, ckpt_id);
+        Ok(ckpt_id)
+    })
+}
+
+/// Load the encoded data of checkpoint `ckpt_id`.
+/// Data stored as diff are rebuilt from the last full data.
+pub(crate) fn load_checkpoint_blob(conn: &SqliteConnection, ckpt_id: i32) -> Result<Blob, DbError> {
+    use crate::schema::checkpoints::dsl::*;
+
+    // follow diffs back to full data
+    let mut chain: Vec<Blob> = vec![];
+    let mut next = Some(ckpt_id);
+    while let Some(i) = next {
+        let (blob, base): (Blob, Option<i32>) = checkpoints
+            .filter(id.eq(i))
+            .select(((data, codec, compression, version), base_id))
+            .first(conn)?;
+        chain.push(blob);
+        next = base;
+    }
+    let keyframe = chain.pop().expect("checkpoint blob");
+    if chain.is_empty() {
+        return Ok(keyframe);
+    }
+
+    let err = |e| DbError::deserialize::<[u8]>(format!("checkpoint {}", ckpt_id), e);
+    let mut raw = keyframe.unpack().map_err(err)?;
+    for blob in chain.iter().rev() {
+        let diff = blob.unpack().map_err(err)?;
+        raw = crate::delta::patch(&raw, &diff).map_err(err)?;
+    }
+    let blob = Blob {
+        data: raw,
+        compression: Compression::None.id(),
+        ..chain.swap_remove(0)
+    };
+    Ok(blob)
+}
 
 pub trait Checkpoint
 where
@@ -10,86 +695,188 @@ where
     // Return a key associated with a group of checkpoints.
     // const CKPT_KEY: &'static str;
 
-    /// Return an unique name as the container for your data.
+    /// Return an unique name as the container for your data. The default is
+    /// derived from the type name, which can be replaced with a stable name
+    /// using [`Register::name`](crate::Register::name).
     fn checkpoint_name() -> String {
-        format!("{}.ckpt", std::any::type_name::<Self>())
+        crate::registry::name_of::<Self>()
     }
 
-    /// Load from the specified checkpoint `n` (ordered by create time).
-    fn from_checkpoint_n(db: &DbConnection, n: i32) -> Result<Self> {
-        use crate::schema::checkpoints::dsl::*;
+    /// Load from the specified checkpoint `n` (ordered by commit sequence).
+    /// Negative `n` counts from the end, e.g. -1 for the latest.
+    fn from_checkpoint_n(db: &DbConnection, n: i32) -> Result<Self, DbError> {
+        let (x, _) = load_checkpoint_n(db, n)?;
+        Ok(x)
+    }
 
-        let conn = db.get();
-        let ckpt_key = Self::checkpoint_name();
-        let ckpts: Vec<i32> = checkpoints
-            .filter(key.eq(&ckpt_key))
-            .select(id)
-            .order(ctime.asc())
-            .load(&*conn)?;
-        let nckpts = ckpts.len();
-        info!("Found {} checkpoints with key {}", nckpts, &ckpt_key);
-
-        // Allow negative index into the list.
-        let k = if n < 0 { nckpts as i32 + n } else { n } as usize;
-        // Avoid panic when n is invalid.
-        if k >= nckpts {
-            bail!("specified checkpoint {} is out of range.", n);
-        }
+    /// Load from the latest checkpoint created at or before `time` in UTC.
+    fn from_checkpoint_at(db: &DbConnection, time: NaiveDateTime) -> Result<Self, DbError> {
+        let (x, _) = load_checkpoint_at(db, time)?;
+        Ok(x)
+    }
 
-        // Get encoded data.
-        let encoded: Vec<u8> = checkpoints.filter(id.eq(&ckpts[k])).select(data).first(&*conn)?;
+    /// Load from the latest checkpoint with `label`.
+    fn from_checkpoint_labeled(db: &DbConnection, label: &str) -> Result<Self, DbError> {
+        Self::from_checkpoint_matching(db, &format!("label={}", label), |m| m.label.as_deref() == Some(label))
+    }
 
-        let x = bincode::deserialize(&encoded)
-            .with_context(|| format!("Failed to deserialize from data for checkpoint: {}/{}", ckpt_key, n))?;
-        Ok(x)
+    /// Load from the latest checkpoint at calculation `step`.
+    fn from_checkpoint_step(db: &DbConnection, step: i64) -> Result<Self, DbError> {
+        Self::from_checkpoint_matching(db, &format!("step={}", step), |m| m.step == Some(step))
     }
 
-    /// Set a checkpoint
-    fn commit_checkpoint(&self, db: &DbConnection) -> Result<()> {
-        use crate::schema::checkpoints::dsl::*;
+    /// Load from the latest checkpoint tagged with `tag`.
+    fn from_checkpoint_tagged(db: &DbConnection, tag: &str) -> Result<Self, DbError> {
+        Self::from_checkpoint_matching(db, &format!("tag={}", tag), |m| m.tags.iter().any(|x| x == tag))
+    }
 
+    /// Load from the latest checkpoint whose metadata satisfy `pred`. `query`
+    /// describes `pred` for error report.
+    fn from_checkpoint_matching(
+        db: &DbConnection,
+        query: &str,
+        pred: impl Fn(&CheckpointMeta) -> bool,
+    ) -> Result<Self, DbError> {
+        let conn = db.reader()?;
         let ckpt_key = Self::checkpoint_name();
-        let conn = db.get();
-
-        let row = (key.eq(&ckpt_key), data.eq(bincode::serialize(&self).unwrap()));
+        let ckpt = find_latest(&conn, db.run(), &crate::registry::names_of::<Self>(), query, pred)?;
+        load_checkpoint(&conn, ckpt.id, &format!("{}/{}", ckpt_key, query))
+    }
 
-        diesel::insert_into(checkpoints)
-            .values(&row)
-            .execute(&*conn)
-            .with_context(|| {
-                format!(
-                    "Failed to save checkpoint\n chk key: {}\n db source: {}",
-                    ckpt_key,
-                    db.database_url()
-                )
-            })?;
+    /// Set a checkpoint
+    fn commit_checkpoint(&self, db: &DbConnection) -> Result<(), DbError> {
+        self.commit_checkpoint_with(db, &CheckpointMeta::default())
+    }
 
+    /// Set a checkpoint with metadata `meta`.
+    fn commit_checkpoint_with(&self, db: &DbConnection, meta: &CheckpointMeta) -> Result<(), DbError> {
+        insert_checkpoint(db, self, meta, None)?;
         Ok(())
     }
 
     /// Restore state from the latest checkpoint.
-    fn restore_from_checkpoint(&mut self, db: &DbConnection) -> Result<()> {
+    fn restore_from_checkpoint(&mut self, db: &DbConnection) -> Result<(), DbError> {
         self.restore_from_checkpoint_n(db, -1)
     }
 
-    /// List available checkpoints in `db`.
-    #[cfg(feature = "adhoc")]
-    fn list_checkpoints(db: &DbConnection) -> Result<()> {
-        use crate::schema::checkpoints::dsl::*;
+    /// Return summaries of available checkpoints in `db`, ordered by slot.
+    fn checkpoints(db: &DbConnection) -> Result<Vec<CheckpointInfo>, DbError> {
+        let conn = db.reader()?;
+        load_checkpoint_infos(&conn, db.run(), &crate::registry::names_of::<Self>())
+    }
 
-        let conn = db.get();
+    /// Load from the latest checkpoint in `branch` of history.
+    fn from_checkpoint_branch(db: &DbConnection, branch: i32) -> Result<Self, DbError> {
+        let conn = db.reader()?;
         let ckpt_key = Self::checkpoint_name();
-        let ckpts: Vec<(i32, String, String)> = checkpoints
-            .filter(key.eq(&ckpt_key))
-            .select((id, key, ctime))
-            .order(ctime.asc())
-            .load(&*conn)?;
-        let nckpts = ckpts.len();
-        info!("Found {} checkpoints with key {}", nckpts, &ckpt_key);
+        let keys = crate::registry::names_of::<Self>();
+        let query = format!("branch={}", branch);
+        let in_branch = slot_query(db.run(), &keys).filter(crate::schema::checkpoints::branch.eq(branch));
+        let ckpt_id = latest_checkpoint_id(&conn, in_branch, &keys)?.ok_or_else(|| DbError::CheckpointNotFound {
+            key: ckpt_key.clone(),
+            query: query.clone(),
+        })?;
+        load_checkpoint(&conn, ckpt_id, &format!("{}/{}", ckpt_key, query))
+    }
+
+    /// Return summaries of branches of checkpoint history, ordered by branch
+    /// number.
+    fn branches(db: &DbConnection) -> Result<Vec<BranchInfo>, DbError> {
+        let ckpts = Self::checkpoints(db)?;
+        let mut branches: BTreeMap<i32, BranchInfo> = BTreeMap::new();
+        for ckpt in ckpts {
+            branches
+                .entry(ckpt.branch)
+                .and_modify(|b| {
+                    b.len += 1;
+                    b.tip = ckpt.clone();
+                })
+                .or_insert_with(|| BranchInfo {
+                    branch: ckpt.branch,
+                    fork_id: ckpt.parent_id,
+                    len: 1,
+                    tip: ckpt.clone(),
+                });
+        }
+        Ok(branches.into_values().collect())
+    }
+
+    /// Return checkpoint `ckpt_id` followed by its ancestors, back to the
+    /// first checkpoint of its history.
+    fn ancestry(db: &DbConnection, ckpt_id: i32) -> Result<Vec<CheckpointInfo>, DbError> {
+        let ckpts: HashMap<i32, CheckpointInfo> = Self::checkpoints(db)?.into_iter().map(|x| (x.id, x)).collect();
+        let mut ancestry = vec![];
+        let mut next = Some(ckpt_id);
+        while let Some(ckpt) = next.and_then(|i| ckpts.get(&i)) {
+            ancestry.push(ckpt.clone());
+            next = ckpt.parent_id;
+        }
+        if ancestry.is_empty() {
+            return Err(DbError::CheckpointNotFound {
+                key: Self::checkpoint_name(),
+                query: format!("id={}", ckpt_id),
+            });
+        }
+        Ok(ancestry)
+    }
+
+    /// Delete checkpoints not to be kept according to `policy`. Return the
+    /// number of deleted checkpoints. Use
+    /// [`DbConnection::vacuum`](crate::DbConnection::vacuum) to reclaim the
+    /// space afterwards.
+    fn prune_checkpoints(db: &DbConnection, policy: RetentionPolicy) -> Result<usize, DbError> {
+        db.transaction(|tx| {
+            let conn = tx.writer();
+            let ckpts = load_checkpoint_infos(&conn, tx.run(), &crate::registry::names_of::<Self>())?;
+            let pruned = policy.select_pruned(&ckpts);
+            if !pruned.is_empty() {
+                info!("Prune {} of {} checkpoints", pruned.len(), ckpts.len());
+            }
+            delete_checkpoints(tx, &pruned)
+        })
+    }
+
+    /// Delete all checkpoints after slot `n`, e.g. to resume from it after
+    /// the calculation went wrong. Negative `n` counts from the end as in
+    /// [`from_checkpoint_n`](Self::from_checkpoint_n). Checkpoints committed
+    /// in a [`CheckpointGroup`](crate::CheckpointGroup) are deleted together
+    /// with the whole group. Return summaries of deleted checkpoints of
+    /// `Self`.
+    fn truncate_after(db: &DbConnection, n: i32) -> Result<Vec<CheckpointInfo>, DbError> {
+        db.transaction(|tx| {
+            let conn = tx.writer();
+            let keys = crate::registry::names_of::<Self>();
+            let ckpt = load_checkpoint_info_n(&conn, tx.run(), &keys, n)?;
+            let deleted = load_checkpoint_info_range(&conn, tx.run(), &keys, ckpt.slot + 1, None)?;
+            if !deleted.is_empty() {
+                info!("Delete {} checkpoints after slot {}", deleted.len(), ckpt.slot);
+            }
+            delete_checkpoints(tx, &deleted.iter().map(|x| x.id).collect_vec())?;
+            Ok(deleted)
+        })
+    }
+
+    /// Delete the checkpoint in slot `n`. Negative `n` counts from the end.
+    /// If it was committed in a [`CheckpointGroup`](crate::CheckpointGroup),
+    /// the whole group is deleted. Return the summary of deleted checkpoint.
+    fn delete_checkpoint(db: &DbConnection, n: i32) -> Result<CheckpointInfo, DbError> {
+        db.transaction(|tx| {
+            let conn = tx.writer();
+            let ckpt = load_checkpoint_info_n(&conn, tx.run(), &crate::registry::names_of::<Self>(), n)?;
+            delete_checkpoints(tx, &[ckpt.id])?;
+            Ok(ckpt)
+        })
+    }
+
+    /// List available checkpoints in `db`.
+    #[cfg(feature = "adhoc")]
+    fn list_checkpoints(db: &DbConnection) -> Result<(), DbError> {
+        let ckpts = Self::checkpoints(db)?;
+        info!("Found {} checkpoints with key {}", ckpts.len(), Self::checkpoint_name());
 
         println!("{:^5}\t{:^}", "slot", "create time");
-        for (i, (_, _, t)) in ckpts.iter().enumerate() {
-            println!("{:^5}\t{:^}", i, t);
+        for ckpt in ckpts {
+            println!("{:^5}\t{:^}", ckpt.slot, ckpt.ctime);
         }
 
         Ok(())
@@ -97,20 +884,28 @@ where
 
     /// Return the number of available checkpoints in database.
     #[cfg(feature = "adhoc")]
-    fn get_number_of_checkpoints(&self, db: &DbConnection) -> Result<i64> {
+    fn get_number_of_checkpoints(&self, db: &DbConnection) -> Result<i64, DbError> {
         use crate::schema::checkpoints::dsl::*;
 
-        let conn = db.get();
-        let ckpt_key = Self::checkpoint_name();
-        let count = checkpoints.filter(key.eq(&ckpt_key)).count().get_result(&*conn)?;
+        let conn = db.reader()?;
+        let count = checkpoints
+            .filter(run.eq(db.run()))
+            .filter(key.eq_any(crate::registry::names_of::<Self>()))
+            .count()
+            .get_result(&*conn)?;
         Ok(count)
     }
 
-    /// Restore state from the specified checkpoint `n` (ordered by create
-    /// time).
-    fn restore_from_checkpoint_n(&mut self, db: &DbConnection, n: i32) -> Result<()> {
-        let x = Self::from_checkpoint_n(db, n)?;
+    /// Restore state from the specified checkpoint `n` (ordered by commit
+    /// sequence).
+    ///
+    /// Checkpoints committed later through `db` or its clones continue from
+    /// the restored one, in a new branch if it is not the latest of its
+    /// branch.
+    fn restore_from_checkpoint_n(&mut self, db: &DbConnection, n: i32) -> Result<(), DbError> {
+        let (x, ckpt) = load_checkpoint_n(db, n)?;
         self.clone_from(&x);
+        db.set_checkpoint_head(&Self::checkpoint_name(), ckpt.id);
         Ok(())
     }
 }
@@ -130,6 +925,50 @@ impl<T> Checkpoint for T where T: Clone + serde::Serialize + serde::de::Deserial
 use gut::cli::*;
 use std::path::{Path, PathBuf};
 
+/// What to do when [`CheckpointDb::restore`] cannot restore from checkpoint.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ResumePolicy {
+    /// Fail unless data are restored.
+    Strict,
+    /// Start fresh if there is no checkpoint to restore from, but fail if the
+    /// checkpoint cannot be read, e.g. for corrupted data or incompatible
+    /// type. Also fail if the checkpoint explicitly selected by slot or time
+    /// is not found.
+    #[default]
+    FreshIfMissing,
+    /// Start fresh on any failure.
+    BestEffort,
+}
+
+/// The result of [`CheckpointDb::restore`].
+#[derive(Debug)]
+pub enum RestoreOutcome {
+    /// Data were restored from the checkpoint.
+    Restored(CheckpointInfo),
+    /// No checkpoint file was set, so there is nothing to restore from.
+    NoCheckpointFile,
+    /// No checkpoint found in the requested slot.
+    Missing(DbError),
+    /// The checkpoint could not be restored, and the error was ignored as
+    /// allowed by [`ResumePolicy::BestEffort`].
+    Failed(DbError),
+}
+
+impl RestoreOutcome {
+    /// Return true if data were restored.
+    pub fn is_restored(&self) -> bool {
+        matches!(self, RestoreOutcome::Restored(_))
+    }
+
+    /// Return the summary of the restored checkpoint.
+    pub fn restored(&self) -> Option<&CheckpointInfo> {
+        match self {
+            RestoreOutcome::Restored(info) => Some(info),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Parser, Default, Clone, Debug)]
 pub struct CheckpointDb {
     /// Path to a checkpoint file for resuming calculation later.
@@ -141,16 +980,83 @@ pub struct CheckpointDb {
     #[structopt(long)]
     chk_slot: Option<i32>,
 
+    /// Restore from the latest checkpoint created at or before the time,
+    /// instead of by slot. The time is either in RFC 3339 format with offset,
+    /// e.g. 2026-10-17T14:30:00+08:00, or in local time as "2026-10-17
+    /// 14:30[:00]".
+    #[structopt(long, value_parser = parse_time, conflicts_with = "chk_slot")]
+    chk_time: Option<NaiveDateTime>,
+
+    /// Which checkpoints to keep when committing new ones: all, last:<n>,
+    /// every:<k>, exp, or bytes:<size> (e.g. bytes:500M). The latest in each
+    /// branch, labeled or tagged checkpoints, and group members are always
+    /// kept.
+    #[structopt(long)]
+    chk_keep: Option<RetentionPolicy>,
+
+    /// Commit checkpoint every n steps, when used with AutoCheckpoint.
+    #[structopt(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    chk_every: Option<usize>,
+
+    /// Commit checkpoint after the interval in seconds since the last one,
+    /// when used with AutoCheckpoint.
+    #[structopt(long, value_parser = parse_interval)]
+    chk_interval: Option<std::time::Duration>,
+
+    /// What to do when restoring from checkpoint fails.
+    #[structopt(long, value_enum, default_value_t)]
+    chk_resume: ResumePolicy,
+
+    /// Name of the run for separating checkpoints of different jobs sharing
+    /// the same checkpoint file. Slots are counted within the run.
+    #[structopt(long)]
+    chk_run: Option<String>,
+
     // internal: database connection
     #[structopt(skip)]
-    db_connection: Option<DbConnection>,
+    pub(crate) db_connection: Option<DbConnection>,
+}
+
+/// Parse interval in seconds for `--chk-interval`.
+fn parse_interval(s: &str) -> Result<std::time::Duration, String> {
+    let t: f64 = s.parse().map_err(|e| format!("invalid number {}: {}", s, e))?;
+    std::time::Duration::try_from_secs_f64(t).map_err(|e| format!("invalid interval {}: {}", s, e))
+}
+
+/// Parse time for `--chk-time`, and return it in UTC.
+fn parse_time(s: &str) -> Result<NaiveDateTime, String> {
+    use chrono::{DateTime, Local, TimeZone};
+
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.naive_utc());
+    }
+    for fmt in [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ] {
+        if let Ok(t) = NaiveDateTime::parse_from_str(s, fmt) {
+            return Local
+                .from_local_datetime(&t)
+                .earliest()
+                .map(|t| t.naive_utc())
+                .ok_or_else(|| format!("invalid local time: {}", s));
+        }
+    }
+    Err(format!(
+        "invalid time: {} (expect e.g. 2026-10-17T14:30:00+08:00 or 2026-10-17 14:30)",
+        s
+    ))
 }
 
 impl CheckpointDb {
     /// Construct Checkpoint from `path` to a file.
     pub fn new<P: AsRef<Path>>(d: P) -> Self {
-        let mut chk = Self::default();
-        chk.chk_file = Some(d.as_ref().to_path_buf());
+        let chk = Self {
+            chk_file: Some(d.as_ref().to_path_buf()),
+            ..Default::default()
+        };
         chk.create()
     }
 
@@ -160,13 +1066,39 @@ impl CheckpointDb {
         self
     }
 
+    /// Construct with checkpoint time, in UTC. See `--chk-time`.
+    pub fn time(mut self, time: NaiveDateTime) -> Self {
+        self.chk_time = Some(time);
+        self
+    }
+
+    /// Set how to handle failures in [`restore`](Self::restore).
+    pub fn resume(mut self, policy: ResumePolicy) -> Self {
+        self.chk_resume = policy;
+        self
+    }
+
+    /// Prune checkpoints according to `policy` on each commit.
+    pub fn retention(mut self, policy: RetentionPolicy) -> Self {
+        self.chk_keep = Some(policy);
+        self
+    }
+
+    /// Commit and restore checkpoints in `run`. See
+    /// [`DbConnection::with_run`].
+    pub fn run(mut self, run: &str) -> Self {
+        self.db_connection = self.db_connection.map(|db| db.with_run(run));
+        self.chk_run = Some(run.into());
+        self
+    }
+
     /// Create missing db_connection field if `chk_file` is not None. Mainly for cmdline uses.
     pub fn create(&self) -> Self {
         if let Some(dbfile) = &self.chk_file {
             let url = format!("{}", dbfile.display());
             let dbc = DbConnection::connect(&url).expect("failed to connect to db src");
             let mut chk = self.clone();
-            chk.db_connection = Some(dbc);
+            chk.db_connection = Some(dbc.with_run(self.chk_run.as_deref().unwrap_or_default()));
             chk
         } else {
             self.to_owned()
@@ -175,26 +1107,52 @@ impl CheckpointDb {
 }
 
 impl CheckpointDb {
-    /// Restore `chain` from checkpoint. Return true if restored successfuly,
-    /// false otherwise.
-    pub fn restore<T: Checkpoint>(&self, data: &mut T) -> Result<bool> {
-        // use resumed `data` from checkpoint if possible
-        if let Some(db) = &self.db_connection {
-            if let Some(n) = self.chk_slot {
-                if let Err(e) = data.restore_from_checkpoint_n(db, n) {
-                    warn!("failed to restore from checkpoint");
-                    dbg!(e);
+    /// Load `T` from checkpoint selected by time, or else by slot (the
+    /// latest by default).
+    fn load_selected<T: Checkpoint>(&self, db: &DbConnection) -> Result<(T, CheckpointInfo), DbError> {
+        match self.chk_time {
+            Some(time) => load_checkpoint_at(db, time),
+            None => load_checkpoint_n(db, self.chk_slot.unwrap_or(-1)),
+        }
+    }
+
+    /// Restore `data` from checkpoint selected by time, or else in the
+    /// selected slot (the latest by default). Failures are handled according
+    /// to the resume policy, and `data` is left untouched unless restored.
+    /// Checkpoints of `T` committed later continue from the restored one.
+    pub fn restore<T: Checkpoint>(&self, data: &mut T) -> Result<RestoreOutcome> {
+        let db = match &self.db_connection {
+            Some(db) => db,
+            None => return Ok(RestoreOutcome::NoCheckpointFile),
+        };
+        match self.load_selected::<T>(db) {
+            Ok((x, info)) => {
+                info!("restored from checkpoint {} in slot {}", info.key, info.slot);
+                data.clone_from(&x);
+                db.set_checkpoint_head(&T::checkpoint_name(), info.id);
+                Ok(RestoreOutcome::Restored(info))
+            }
+            Err(e) => match self.chk_resume {
+                ResumePolicy::Strict => Err(e).context("failed to restore from checkpoint"),
+                // an explicitly requested checkpoint must exist
+                ResumePolicy::FreshIfMissing
+                    if !e.is_not_found() || self.chk_slot.is_some() || self.chk_time.is_some() =>
+                {
+                    Err(e).context("failed to restore from checkpoint")
                 }
-            } else {
-                if let Err(e) = data.restore_from_checkpoint(db) {
-                    warn!("failed to restore from checkpoint");
-                    dbg!(e);
-                    return Ok(false);
+                ResumePolicy::FreshIfMissing => {
+                    info!("no checkpoint to restore from: {}", e);
+                    Ok(RestoreOutcome::Missing(e))
                 }
-            }
-            Ok(true)
-        } else {
-            Ok(false)
+                ResumePolicy::BestEffort if e.is_not_found() => {
+                    info!("no checkpoint to restore from: {}", e);
+                    Ok(RestoreOutcome::Missing(e))
+                }
+                ResumePolicy::BestEffort => {
+                    warn!("failed to restore from checkpoint, start fresh: {}", e);
+                    Ok(RestoreOutcome::Failed(e))
+                }
+            },
         }
     }
 
@@ -204,10 +1162,19 @@ impl CheckpointDb {
         self.load_from_latest()
     }
 
-    /// Load latest struct `T` from checkpoint
+    /// Load latest struct `T` from checkpoint, or from the one selected by
+    /// time or slot if set.
     pub fn load_from_latest<T: Checkpoint>(&self) -> Result<T> {
-        let n = self.chk_slot.unwrap_or(-1);
-        self.load_from_slot_n(n)
+        let db = self.db_connection.as_ref().expect("no db connection");
+        let (x, _) = self.load_selected(db)?;
+        Ok(x)
+    }
+
+    /// Load struct `T` from the latest checkpoint created at or before `time`
+    /// in UTC.
+    pub fn load_from_time<T: Checkpoint>(&self, time: NaiveDateTime) -> Result<T> {
+        let db = self.db_connection.as_ref().expect("no db connection");
+        Ok(T::from_checkpoint_at(db, time)?)
     }
 
     /// Load struct `T` from checkpoint in `slot`
@@ -216,25 +1183,126 @@ impl CheckpointDb {
         Ok(T::from_checkpoint_n(db, slot)?)
     }
 
+    /// Load struct `T` from the latest checkpoint with `label`.
+    pub fn load_from_label<T: Checkpoint>(&self, label: &str) -> Result<T> {
+        let db = self.db_connection.as_ref().expect("no db connection");
+        Ok(T::from_checkpoint_labeled(db, label)?)
+    }
+
+    /// Load struct `T` from the latest checkpoint at calculation `step`.
+    pub fn load_from_step<T: Checkpoint>(&self, step: i64) -> Result<T> {
+        let db = self.db_connection.as_ref().expect("no db connection");
+        Ok(T::from_checkpoint_step(db, step)?)
+    }
+
+    /// Load struct `T` from the latest checkpoint tagged with `tag`.
+    pub fn load_from_tag<T: Checkpoint>(&self, tag: &str) -> Result<T> {
+        let db = self.db_connection.as_ref().expect("no db connection");
+        Ok(T::from_checkpoint_tagged(db, tag)?)
+    }
+
     /// Commit a checkpoint into database. Return true if committed, false
     /// otherwise.
     pub fn commit<T: Checkpoint>(&self, data: &T) -> Result<bool> {
+        self.commit_with(data, &CheckpointMeta::default())
+    }
+
+    /// Commit a checkpoint with metadata `meta` into database. Return true if
+    /// committed, false otherwise.
+    pub fn commit_with<T: Checkpoint>(&self, data: &T, meta: &CheckpointMeta) -> Result<bool> {
         if let Some(db) = &self.db_connection {
-            data.commit_checkpoint(db)?;
+            data.commit_checkpoint_with(db, meta)?;
+            if let Some(policy) = self.chk_keep {
+                T::prune_checkpoints(db, policy)?;
+            }
             Ok(true)
         } else {
             Ok(false)
         }
     }
 
-    /// List available checkpoints in database.
-    #[cfg(feature = "adhoc")]
-    pub fn list<T: Checkpoint>(&self) -> Result<bool> {
+    /// Return an [`AutoCheckpoint`](crate::AutoCheckpoint) committing
+    /// checkpoints as set by `--chk-every` and `--chk-interval`.
+    pub fn auto(&self) -> crate::AutoCheckpoint {
+        let mut auto = crate::AutoCheckpoint::new(self.clone());
+        if let Some(n) = self.chk_every {
+            auto = auto.every(n);
+        }
+        if let Some(t) = self.chk_interval {
+            auto = auto.interval(t);
+        }
+        auto
+    }
+
+    /// Return a writer committing checkpoints in background, with the same
+    /// retention policy. Return None if no database is set.
+    pub fn background_writer(&self) -> Option<crate::CheckpointWriter> {
+        let db = self.db_connection.as_ref()?;
+        let writer = crate::CheckpointWriter::new(db);
+        match self.chk_keep {
+            Some(policy) => Some(writer.retention(policy)),
+            None => Some(writer),
+        }
+    }
+
+    /// Delete checkpoints of `T` according to the retention policy. Return the
+    /// number of deleted checkpoints.
+    pub fn prune<T: Checkpoint>(&self) -> Result<usize> {
+        match (&self.db_connection, self.chk_keep) {
+            (Some(db), Some(policy)) => Ok(T::prune_checkpoints(db, policy)?),
+            _ => Ok(0),
+        }
+    }
+
+    /// Delete checkpoints of `T` after `slot`. Return summaries of deleted
+    /// checkpoints, or of those to be deleted without deleting anything if
+    /// `dry_run` is true.
+    pub fn truncate_after<T: Checkpoint>(&self, slot: i32, dry_run: bool) -> Result<Vec<CheckpointInfo>> {
+        let db = match &self.db_connection {
+            Some(db) => db,
+            None => return Ok(vec![]),
+        };
+        if dry_run {
+            let conn = db.reader()?;
+            let keys = crate::registry::names_of::<T>();
+            let ckpt = load_checkpoint_info_n(&conn, db.run(), &keys, slot)?;
+            Ok(load_checkpoint_info_range(&conn, db.run(), &keys, ckpt.slot + 1, None)?)
+        } else {
+            Ok(T::truncate_after(db, slot)?)
+        }
+    }
+
+    /// Delete the checkpoint of `T` in `slot`. Return its summary, without
+    /// deleting it if `dry_run` is true. Return None if no database is set.
+    pub fn delete_checkpoint<T: Checkpoint>(&self, slot: i32, dry_run: bool) -> Result<Option<CheckpointInfo>> {
+        let db = match &self.db_connection {
+            Some(db) => db,
+            None => return Ok(None),
+        };
+        if dry_run {
+            let conn = db.reader()?;
+            let ckpt = load_checkpoint_info_n(&conn, db.run(), &crate::registry::names_of::<T>(), slot)?;
+            Ok(Some(ckpt))
+        } else {
+            Ok(Some(T::delete_checkpoint(db, slot)?))
+        }
+    }
+
+    /// Reclaim the space of deleted checkpoints in database.
+    pub fn vacuum(&self) -> Result<()> {
         if let Some(db) = &self.db_connection {
-            T::list_checkpoints(db)?;
-            Ok(true)
+            db.vacuum()?;
+        }
+        Ok(())
+    }
+
+    /// Return summaries of available checkpoints of `T` in database, ordered
+    /// by slot. Return an empty list if no database is set.
+    pub fn list<T: Checkpoint>(&self) -> Result<Vec<CheckpointInfo>> {
+        if let Some(db) = &self.db_connection {
+            Ok(T::checkpoints(db)?)
         } else {
-            Ok(false)
+            Ok(vec![])
         }
     }
 }
@@ -256,6 +1324,11 @@ mod test {
         let url = format!("{}", tmpdb.display());
         let db = DbConnection::connect(&url)?;
 
+        // no checkpoint yet
+        let e = TestObject::from_checkpoint_n(&db, -1).unwrap_err();
+        assert!(matches!(e, DbError::SlotOutOfRange { len: 0, .. }));
+        assert!(e.is_not_found());
+
         // commit checkpoint
         let mut x = TestObject { data: -12.0 };
         x.commit_checkpoint(&db)?;
@@ -277,6 +1350,144 @@ mod test {
         x.restore_from_checkpoint_n(&db, 1)?;
         assert_eq!(x.data, 1.0);
 
+        let ckpts = TestObject::checkpoints(&db)?;
+        assert_eq!(ckpts.len(), 3);
+        assert_eq!(ckpts[2].slot, 2);
+        assert_eq!(ckpts[2].key, TestObject::checkpoint_name());
+        assert_eq!(ckpts[2].codec, Some(CodecId::Bincode));
+        assert_eq!(ckpts[2].size, 8);
+        assert_eq!(ckpts.iter().map(|x| x.seq).collect_vec(), [1, 2, 3]);
+
+        // many commits in the same second
+        for i in 0..20 {
+            x.data = i as f64;
+            x.commit_checkpoint(&db)?;
+        }
+        for i in 0..20 {
+            x.restore_from_checkpoint_n(&db, i + 3)?;
+            assert_eq!(x.data, i as f64);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint_seq_migration() -> Result<()> {
+        let tdir = tempfile::tempdir()?;
+        let tmpdb = tdir.path().join("test.sqlite");
+        let url = format!("{}", tmpdb.display());
+
+        // setup database in the schema before checkpoint sequence
+        {
+            use diesel::connection::SimpleConnection;
+
+            let conn = SqliteConnection::establish(&url)?;
+            conn.batch_execute(include_str!("../migrations/2019-08-09-025033_init/up.sql"))?;
+            conn.batch_execute(include_str!("../migrations/2026-10-18-080000_codec/up.sql"))?;
+            conn.batch_execute(include_str!("../migrations/2026-10-18-090000_compression/up.sql"))?;
+            conn.batch_execute(include_str!("../migrations/2026-10-18-100000_version/up.sql"))?;
+            conn.batch_execute(
+                "CREATE TABLE __diesel_schema_migrations (
+                   version VARCHAR(50) PRIMARY KEY NOT NULL,
+                   run_on TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP);
+                 INSERT INTO __diesel_schema_migrations (version) VALUES
+                   ('20190809025033'), ('20261018080000'), ('20261018090000'), ('20261018100000');",
+            )?;
+
+            use crate::schema::checkpoints::dsl::*;
+            for i in 0..3 {
+                let encoded = bincode::serialize(&TestObject { data: i as f64 })?;
+                let row = (
+                    key.eq(TestObject::checkpoint_name()),
+                    data.eq(encoded),
+                    ctime.eq(diesel::dsl::sql("'2020-01-01 00:00:00'")),
+                );
+                diesel::insert_into(checkpoints).values(row).execute(&conn)?;
+            }
+        }
+
+        let db = DbConnection::connect(&url)?;
+        let ckpts = TestObject::checkpoints(&db)?;
+        assert_eq!(ckpts.iter().map(|x| x.seq).collect_vec(), [1, 2, 3]);
+        assert_eq!(TestObject::from_checkpoint_n(&db, -1)?.data, 2.0);
+        TestObject { data: 3.0 }.commit_checkpoint(&db)?;
+        assert_eq!(TestObject::from_checkpoint_n(&db, -1)?.data, 3.0);
+        assert_eq!(TestObject::checkpoints(&db)?[3].seq, 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_time() {
+        use chrono::{Local, TimeZone};
+
+        let t = parse_time("2026-10-17T14:30:00+08:00").unwrap();
+        assert_eq!(t.to_string(), "2026-10-17 06:30:00");
+        assert_eq!(
+            parse_time("2026-10-17T06:30:00.5Z").unwrap().to_string(),
+            "2026-10-17 06:30:00.500"
+        );
+
+        // local time
+        let local = Local.with_ymd_and_hms(2026, 10, 17, 14, 30, 0).unwrap().naive_utc();
+        assert_eq!(parse_time("2026-10-17 14:30").unwrap(), local);
+        assert_eq!(parse_time("2026-10-17 14:30:00").unwrap(), local);
+        assert_eq!(parse_time("2026-10-17T14:30:00").unwrap(), local);
+
+        assert!(parse_time("14:30").is_err());
+        assert!(parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_restore_policy() -> Result<()> {
+        let tdir = tempfile::tempdir()?;
+        let tmpdb = tdir.path().join("test.sqlite");
+        let path = format!("{}", tmpdb.display());
+
+        let mut x = TestObject { data: -1.0 };
+        // no checkpoint file
+        let chk = CheckpointDb::try_parse_from(["test"])?.create();
+        assert!(matches!(chk.restore(&mut x)?, RestoreOutcome::NoCheckpointFile));
+
+        // no checkpoint yet
+        let chk = CheckpointDb::try_parse_from(["test", "--chk-file", &path])?.create();
+        assert!(matches!(chk.restore(&mut x)?, RestoreOutcome::Missing(_)));
+        let chk = CheckpointDb::try_parse_from(["test", "--chk-file", &path, "--chk-resume", "strict"])?.create();
+        assert!(chk.restore(&mut x).is_err());
+        assert_eq!(x.data, -1.0);
+
+        chk.commit(&TestObject { data: 1.0 })?;
+        let outcome = chk.restore(&mut x)?;
+        assert_eq!(outcome.restored().map(|c| c.slot), Some(0));
+        assert_eq!(x.data, 1.0);
+
+        // invalid slot
+        let chk = chk.slot(5);
+        assert!(chk.restore(&mut x).is_err());
+        let chk = chk.resume(ResumePolicy::FreshIfMissing);
+        assert!(chk.restore(&mut x).is_err());
+        let early = chrono::NaiveDate::from_ymd_opt(2000, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        assert!(chk.clone().time(early).restore(&mut x).is_err());
+        let chk = chk.resume(ResumePolicy::BestEffort);
+        assert!(matches!(chk.restore(&mut x)?, RestoreOutcome::Missing(_)));
+        let chk = chk.resume(ResumePolicy::FreshIfMissing);
+
+        // corrupted data
+        {
+            use crate::schema::checkpoints::dsl::*;
+            let row = (key.eq(TestObject::checkpoint_name()), seq.eq(2), data.eq(vec![1u8]));
+            let db = DbConnection::connect(&path)?;
+            diesel::insert_into(checkpoints).values(&row).execute(&*db.writer())?;
+        }
+        let chk = chk.slot(-1);
+        assert!(chk.restore(&mut x).is_err());
+        let chk = chk.resume(ResumePolicy::BestEffort);
+        assert!(matches!(chk.restore(&mut x)?, RestoreOutcome::Failed(_)));
+        assert_eq!(x.data, 1.0);
+
         Ok(())
     }
 }
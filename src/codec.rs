@@ -0,0 +1,167 @@
+// [[file:../database.note::*codec][codec:1]]
+use crate::error::BoxedError;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// The identifier of a codec, which is stored along with the encoded data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CodecId {
+    #[default]
+    Bincode = 0,
+    Json = 1,
+    MessagePack = 2,
+    Cbor = 3,
+}
+
+impl CodecId {
+    pub(crate) fn from_i32(id: i32) -> Option<Self> {
+        let codec = match id {
+            0 => CodecId::Bincode,
+            1 => CodecId::Json,
+            2 => CodecId::MessagePack,
+            3 => CodecId::Cbor,
+            _ => return None,
+        };
+        Some(codec)
+    }
+
+    pub(crate) fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, BoxedError> {
+        match self {
+            CodecId::Bincode => Bincode::encode(value),
+            CodecId::Json => Json::encode(value),
+            CodecId::MessagePack => MessagePack::encode(value),
+            CodecId::Cbor => Cbor::encode(value),
+        }
+    }
+
+    pub(crate) fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BoxedError> {
+        match self {
+            CodecId::Bincode => Bincode::decode(bytes),
+            CodecId::Json => Json::decode(bytes),
+            CodecId::MessagePack => MessagePack::decode(bytes),
+            CodecId::Cbor => Cbor::decode(bytes),
+        }
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for super::Bincode {}
+    impl Sealed for super::Json {}
+    impl Sealed for super::MessagePack {}
+    impl Sealed for super::Cbor {}
+}
+
+/// A serialization format for stored data. Only the codecs defined in this
+/// module are supported, since data are decoded by the [`CodecId`] stored
+/// along with them, so the trait cannot be implemented outside this crate.
+pub trait Codec: sealed::Sealed {
+    /// The identifier stored along with the encoded data.
+    const ID: CodecId;
+
+    /// Encode `value` into bytes.
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, BoxedError>;
+
+    /// Decode a value from `bytes`.
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, BoxedError>;
+}
+
+/// The compact binary format of [bincode](https://docs.rs/bincode). The
+/// default codec.
+#[derive(Debug, Clone, Copy)]
+pub struct Bincode;
+
+/// The JSON format, readable by most other tools.
+#[derive(Debug, Clone, Copy)]
+pub struct Json;
+
+/// The [MessagePack](https://msgpack.org) format. Struct fields are encoded
+/// by name.
+#[derive(Debug, Clone, Copy)]
+pub struct MessagePack;
+
+/// The [CBOR](https://cbor.io) format.
+#[derive(Debug, Clone, Copy)]
+pub struct Cbor;
+
+impl Codec for Bincode {
+    const ID: CodecId = CodecId::Bincode;
+
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, BoxedError> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, BoxedError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+impl Codec for Json {
+    const ID: CodecId = CodecId::Json;
+
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, BoxedError> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, BoxedError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+impl Codec for MessagePack {
+    const ID: CodecId = CodecId::MessagePack;
+
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, BoxedError> {
+        Ok(rmp_serde::to_vec_named(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, BoxedError> {
+        Ok(rmp_serde::from_slice(bytes)?)
+    }
+}
+
+impl Codec for Cbor {
+    const ID: CodecId = CodecId::Cbor;
+
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, BoxedError> {
+        let mut buf = vec![];
+        ciborium::ser::into_writer(value, &mut buf)?;
+        Ok(buf)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, BoxedError> {
+        Ok(ciborium::de::from_reader(bytes)?)
+    }
+}
+// codec:1 ends here
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct TestObject {
+        name: String,
+        data: Vec<f64>,
+    }
+
+    #[test]
+    fn test_codecs() {
+        let x = TestObject {
+            name: "x".into(),
+            data: vec![1.0, -2.5],
+        };
+
+        for id in [CodecId::Bincode, CodecId::Json, CodecId::MessagePack, CodecId::Cbor] {
+            assert_eq!(CodecId::from_i32(id as i32), Some(id));
+            let bytes = id.encode(&x).unwrap();
+            let y: TestObject = id.decode(&bytes).unwrap();
+            assert_eq!(x, y);
+        }
+        assert!(CodecId::from_i32(-1).is_none());
+
+        let bytes = Json::encode(&x).unwrap();
+        assert_eq!(bytes, br#"{"name":"x","data":[1.0,-2.5]}"#);
+    }
+}
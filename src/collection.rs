@@ -1,120 +1,317 @@
-use crate::schema::*;
+use crate::blob::Blob;
+use crate::schema::kvstore;
 use crate::*;
 
-use gut::prelude::*;
+use diesel::sqlite::Sqlite;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+/// Query for items of `T` ordered by key. Items under an alias are hidden by
+/// those with the same key under the current name.
+fn items_query<'a, T: Collection>() -> kvstore::BoxedQuery<'a, Sqlite> {
+    use crate::schema::kvstore::dsl::*;
+    use diesel::dsl::sql;
+    use diesel::sql_types::{Bool, Text};
+
+    let cname = T::collection_name();
+    let shadowed = sql::<Bool>("NOT EXISTS (SELECT 1 FROM kvstore k WHERE k.collection = ")
+        .bind::<Text, _>(cname.clone())
+        .sql(" AND k.key = kvstore.key)");
+    kvstore
+        .filter(collection.eq_any(crate::registry::names_of::<T>()))
+        .filter(collection.eq(cname).or(shadowed))
+        .order(key.asc())
+        .into_boxed()
+}
+
+/// Load and decode items of `T` selected by `query`.
+fn load_items<T: Collection>(
+    conn: &SqliteConnection,
+    query: kvstore::BoxedQuery<'_, Sqlite>,
+) -> Result<Vec<(String, T)>, DbError> {
+    use crate::schema::kvstore::dsl::*;
+
+    let cname = T::collection_name();
+    let rows: Vec<(String, Blob)> = query.select((key, (data, codec, compression, version))).load(conn)?;
+    rows.into_iter()
+        .map(|(obj_key, blob)| {
+            let x = blob.decode(&format!("{}/{}", cname, obj_key))?;
+            Ok((obj_key, x))
+        })
+        .collect()
+}
 
 pub trait Collection
 where
     Self: serde::Serialize + serde::de::DeserializeOwned,
 {
-    /// Return an unique name as the container for your data.
+    /// Return an unique name as the container for your data. The default is
+    /// derived from the type name, which can be replaced with a stable name
+    /// using [`Register::name`](crate::Register::name).
     fn collection_name() -> String {
-        format!("{}.ckpt", std::any::type_name::<Self>())
+        crate::registry::name_of::<Self>()
     }
 
     /// Put the object into collection with an associated key. If `new_key`
     /// already exits, the database will attempt to replace the offending row
     /// instead.
-    fn put_into_collection(&self, db: &DbConnection, new_key: &str) -> Result<()> {
+    fn put_into_collection(&self, db: &DbConnection, new_key: &str) -> Result<(), DbError> {
         use crate::schema::kvstore::dsl::*;
 
-        let conn = db.get();
+        let conn = db.writer();
         let cname = &Self::collection_name();
 
+        let blob = Blob::encode(self, db)?;
         let row = (
             collection.eq(cname),
             key.eq(new_key),
-            data.eq(bincode::serialize(&self).unwrap()),
+            data.eq(blob.data),
+            codec.eq(blob.codec),
+            compression.eq(blob.compression),
+            version.eq(blob.version),
         );
 
-        diesel::replace_into(kvstore)
-            .values(&row)
-            .execute(&*conn)
-            .with_context(|| {
-                format!(
-                    "Failed to put data into collection {} with key {}\n db source: {}",
-                    cname,
-                    new_key,
-                    db.database_url()
-                )
-            })?;
+        diesel::replace_into(kvstore).values(&row).execute(&*conn)?;
 
         Ok(())
     }
 
     /// Return the object in this collection by `key`.
-    fn get_from_collection(db: &DbConnection, obj_key: &str) -> Result<Self> {
+    fn get_from_collection(db: &DbConnection, obj_key: &str) -> Result<Self, DbError> {
         use crate::schema::kvstore::dsl::*;
 
-        let conn = db.get();
+        let conn = db.reader()?;
         let cname = &Self::collection_name();
-        let encoded: Vec<u8> = kvstore
-            .filter(collection.eq(&cname))
+        // prefer current name to aliases
+        let blob: Blob = kvstore
+            .filter(collection.eq_any(crate::registry::names_of::<Self>()))
             .filter(key.eq(&obj_key))
-            .select(data)
-            .first(&*conn)?;
-
-        let x = bincode::deserialize(&encoded)
-            .with_context(|| format!("Failed to deserialize data for {}/{}", cname, obj_key))?;
+            .order(collection.eq(cname).desc())
+            .select((data, codec, compression, version))
+            .first(&*conn)
+            .optional()?
+            .ok_or_else(|| DbError::KeyNotFound {
+                key: format!("{}/{}", cname, obj_key),
+            })?;
 
-        Ok(x)
+        blob.decode(&format!("{}/{}", cname, obj_key))
     }
 
     /// Delete the object in this collection by `key`.
-    fn del_from_collection(db: &DbConnection, obj_key: &str) -> Result<()> {
+    fn del_from_collection(db: &DbConnection, obj_key: &str) -> Result<(), DbError> {
         use crate::schema::kvstore::dsl::*;
 
-        let conn = db.get();
-        let cname = &Self::collection_name();
-        diesel::delete(kvstore.filter(collection.eq(&cname)).filter(key.eq(&obj_key))).execute(&*conn)?;
+        let conn = db.writer();
+        let cnames = crate::registry::names_of::<Self>();
+        diesel::delete(kvstore.filter(collection.eq_any(cnames)).filter(key.eq(&obj_key))).execute(&*conn)?;
 
         Ok(())
     }
 
     /// Remove all objects in this collection.
-    fn remove_collection(db: &DbConnection) -> Result<()> {
+    fn remove_collection(db: &DbConnection) -> Result<(), DbError> {
         use crate::schema::kvstore::dsl::*;
 
-        let conn = db.get();
-        let cname = &Self::collection_name();
-        diesel::delete(kvstore.filter(collection.eq(&cname))).execute(&*conn)?;
+        let conn = db.writer();
+        let cnames = crate::registry::names_of::<Self>();
+        diesel::delete(kvstore.filter(collection.eq_any(cnames))).execute(&*conn)?;
         Ok(())
     }
 
-    /// List all items in the collection.
-    fn list_collection(db: &DbConnection) -> Result<Vec<Self>> {
+    /// List all items in the collection. See [`iter`](Self::iter) for
+    /// iterating over large collections.
+    fn list_collection(db: &DbConnection) -> Result<Vec<Self>, DbError> {
+        let conn = db.reader()?;
+        let items = load_items(&conn, items_query::<Self>())?;
+        Ok(items.into_iter().map(|(_, x)| x).collect())
+    }
+
+    /// Return a lazy iterator over items in the collection as `(key, value)`
+    /// pairs in ascending order of key. Items are fetched in batches, so
+    /// that huge collections can be processed in constant memory.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use gosh_database::prelude::*;
+    /// use gosh_database::DbConnection;
+    ///
+    /// let db = DbConnection::connect("/tmp/test.sqlite").unwrap();
+    /// for item in Vec::<f64>::iter(&db).batch_size(1000).skip_invalid() {
+    ///     let (key, x) = item.unwrap();
+    /// }
+    /// ```
+    fn iter(db: &DbConnection) -> CollectionIter<Self> {
+        CollectionIter {
+            db: db.clone(),
+            batch_size: 100,
+            skip_invalid: false,
+            cursor: None,
+            batch: VecDeque::new(),
+            done: false,
+            _type: PhantomData,
+        }
+    }
+
+    /// Return keys of all items in the collection in ascending order, without
+    /// loading their data.
+    fn keys(db: &DbConnection) -> Result<Vec<String>, DbError> {
         use crate::schema::kvstore::dsl::*;
 
-        let conn = db.get();
-        let cname = &Self::collection_name();
-        let list: Vec<(String, Vec<u8>)> = kvstore.filter(collection.eq(&cname)).select((key, data)).load(&*conn)?;
+        let conn = db.reader()?;
+        let keys = items_query::<Self>().select(key).load(&*conn)?;
+        Ok(keys)
+    }
 
-        let mut items = vec![];
-        for (obj_key, encoded) in list {
-            let x = bincode::deserialize(&encoded)
-                .with_context(|| format!("Failed to deserialize data for {}/{}", cname, obj_key))?;
-            items.push(x);
-        }
-        Ok(items)
+    /// Return items with key starting with `prefix` as `(key, value)` pairs,
+    /// in ascending order of key.
+    fn scan_prefix(db: &DbConnection, prefix: &str) -> Result<Vec<(String, Self)>, DbError> {
+        use crate::schema::kvstore::dsl::*;
+        use diesel::dsl::sql;
+        use diesel::sql_types::{Bool, Text};
+
+        let conn = db.reader()?;
+        // LIKE is case insensitive in SQLite
+        let starts_with = sql::<Bool>("substr(kvstore.key, 1, length(")
+            .bind::<Text, _>(prefix.to_owned())
+            .sql(")) = ")
+            .bind::<Text, _>(prefix.to_owned());
+        let query = items_query::<Self>()
+            .filter(key.ge(prefix.to_owned()))
+            .filter(starts_with);
+        load_items(&conn, query)
     }
 
-    /// Return the number of items in collection.
-    fn collection_size(db: &DbConnection) -> Result<i64> {
+    /// Return items with key in `range` as `(key, value)` pairs, in ascending
+    /// order of key, e.g. `range(db, "a".."c")`.
+    fn range<'r, R: RangeBounds<&'r str>>(db: &DbConnection, range: R) -> Result<Vec<(String, Self)>, DbError> {
         use crate::schema::kvstore::dsl::*;
 
-        let conn = db.get();
-        let cname = &Self::collection_name();
-        let count = kvstore.filter(collection.eq(&cname)).count().get_result(&*conn)?;
+        let conn = db.reader()?;
+        let mut query = items_query::<Self>();
+        query = match range.start_bound() {
+            Bound::Included(k) => query.filter(key.ge(k.to_string())),
+            Bound::Excluded(k) => query.filter(key.gt(k.to_string())),
+            Bound::Unbounded => query,
+        };
+        query = match range.end_bound() {
+            Bound::Included(k) => query.filter(key.le(k.to_string())),
+            Bound::Excluded(k) => query.filter(key.lt(k.to_string())),
+            Bound::Unbounded => query,
+        };
+        load_items(&conn, query)
+    }
+
+    /// Return at most `limit` items after skipping the first `offset` ones,
+    /// as `(key, value)` pairs in ascending order of key.
+    fn list_page(db: &DbConnection, offset: usize, limit: usize) -> Result<Vec<(String, Self)>, DbError> {
+        let conn = db.reader()?;
+        let query = items_query::<Self>().offset(offset as i64).limit(limit as i64);
+        load_items(&conn, query)
+    }
 
-        // conn.execute(&format!("DROP TABLE {}", "kvstore")).unwrap();
-        // conn.execute("SELECT COUNT(*) FROM kvstore").unwrap();
+    /// Return at most `limit` items with key after `cursor`, as `(key, value)`
+    /// pairs in ascending order of key. Pass the last key of a page as
+    /// `cursor` for the next page, or None for the first page. Unlike
+    /// [`list_page`](Self::list_page), pages are not shifted by items
+    /// inserted or deleted meanwhile.
+    fn list_after(db: &DbConnection, cursor: Option<&str>, limit: usize) -> Result<Vec<(String, Self)>, DbError> {
+        use crate::schema::kvstore::dsl::*;
+
+        let conn = db.reader()?;
+        let mut query = items_query::<Self>().limit(limit as i64);
+        if let Some(cursor) = cursor {
+            query = query.filter(key.gt(cursor.to_owned()));
+        }
+        load_items(&conn, query)
+    }
 
+    /// Return the number of items in collection.
+    fn collection_size(db: &DbConnection) -> Result<i64, DbError> {
+        let conn = db.reader()?;
+        let count = items_query::<Self>().count().get_result(&*conn)?;
         Ok(count)
     }
 }
 
 impl<T> Collection for T where T: serde::Serialize + serde::de::DeserializeOwned {}
 
+/// Lazy iterator over items in a collection, see [`Collection::iter`].
+///
+/// Yields an error for each item failing to decode unless
+/// [`skip_invalid`](Self::skip_invalid) is set. Iteration stops after an
+/// error from the database.
+pub struct CollectionIter<T> {
+    db: DbConnection,
+    batch_size: usize,
+    skip_invalid: bool,
+    // the last key fetched
+    cursor: Option<String>,
+    batch: VecDeque<(String, Blob)>,
+    done: bool,
+    _type: PhantomData<T>,
+}
+
+impl<T: Collection> CollectionIter<T> {
+    /// Fetch `n` items at a time from database (100 by default).
+    pub fn batch_size(mut self, n: usize) -> Self {
+        self.batch_size = n.max(1);
+        self
+    }
+
+    /// Skip items failing to decode, e.g. of incompatible type, instead of
+    /// yielding errors. Skipped items are logged as warnings.
+    pub fn skip_invalid(mut self) -> Self {
+        self.skip_invalid = true;
+        self
+    }
+
+    /// Fetch next batch of rows after the cursor.
+    fn fetch(&mut self) -> Result<(), DbError> {
+        use crate::schema::kvstore::dsl::*;
+
+        let conn = self.db.reader()?;
+        let mut query = items_query::<T>().limit(self.batch_size as i64);
+        if let Some(cursor) = &self.cursor {
+            query = query.filter(key.gt(cursor.clone()));
+        }
+        let rows: Vec<(String, Blob)> = query.select((key, (data, codec, compression, version))).load(&*conn)?;
+        if rows.len() < self.batch_size {
+            self.done = true;
+        }
+        if let Some((last, _)) = rows.last() {
+            self.cursor = Some(last.clone());
+        }
+        self.batch.extend(rows);
+        Ok(())
+    }
+}
+
+impl<T: Collection> Iterator for CollectionIter<T> {
+    type Item = Result<(String, T), DbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.batch.is_empty() {
+                if self.done {
+                    return None;
+                }
+                if let Err(e) = self.fetch() {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+            let (obj_key, blob) = self.batch.pop_front()?;
+            match blob.decode(&format!("{}/{}", T::collection_name(), obj_key)) {
+                Ok(x) => return Some(Ok((obj_key, x))),
+                Err(e) if self.skip_invalid => warn!("skip invalid item: {}", e),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -154,6 +351,107 @@ mod test {
         let x_new = TestObject::get_from_collection(&db, "test1")?;
         assert_eq!(x.data, x_new.data);
 
+        let e = TestObject::get_from_collection(&db, "test2").unwrap_err();
+        assert!(matches!(e, DbError::KeyNotFound { .. }));
+
+        // corrupted data
+        {
+            use crate::schema::kvstore::dsl::*;
+            let row = (
+                collection.eq(TestObject::collection_name()),
+                key.eq("bad"),
+                data.eq(vec![1u8]),
+            );
+            diesel::insert_into(kvstore).values(&row).execute(&*db.writer())?;
+        }
+        let e = TestObject::get_from_collection(&db, "bad").unwrap_err();
+        assert!(matches!(e, DbError::Deserialize { .. }));
+        assert!(!e.is_not_found());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collection_scan() -> Result<()> {
+        let tdir = tempfile::tempdir()?;
+        let tmpdb = tdir.path().join("test.sqlite");
+        let db = DbConnection::connect(&format!("{}", tmpdb.display()))?;
+
+        let keys = ["b/2", "a/1", "a/2", "A/3", "b/1", "a%", "c"];
+        for (i, k) in keys.iter().enumerate() {
+            TestObject { data: i as f64 }.put_into_collection(&db, k)?;
+        }
+        let key_of = |items: Vec<(String, TestObject)>| items.into_iter().map(|(k, _)| k).collect_vec();
+
+        assert_eq!(TestObject::keys(&db)?, ["A/3", "a%", "a/1", "a/2", "b/1", "b/2", "c"]);
+        let items = TestObject::scan_prefix(&db, "a/")?;
+        assert_eq!(items[0].0, "a/1");
+        assert_eq!(items[0].1.data, 1.0);
+        assert_eq!(key_of(items), ["a/1", "a/2"]);
+        assert_eq!(key_of(TestObject::scan_prefix(&db, "a%")?), ["a%"]);
+        assert_eq!(TestObject::scan_prefix(&db, "")?.len(), 7);
+        assert!(TestObject::scan_prefix(&db, "d")?.is_empty());
+
+        assert_eq!(key_of(TestObject::range(&db, "a/2".."b/2")?), ["a/2", "b/1"]);
+        assert_eq!(key_of(TestObject::range(&db, "b/1"..)?), ["b/1", "b/2", "c"]);
+        assert_eq!(key_of(TestObject::range(&db, ..="a%")?), ["A/3", "a%"]);
+        assert_eq!(TestObject::range(&db, ..)?.len(), 7);
+
+        assert_eq!(key_of(TestObject::list_page(&db, 2, 3)?), ["a/1", "a/2", "b/1"]);
+        assert!(TestObject::list_page(&db, 7, 3)?.is_empty());
+        let mut cursor = None;
+        let mut pages = vec![];
+        loop {
+            let page = key_of(TestObject::list_after(&db, cursor.as_deref(), 3)?);
+            if page.is_empty() {
+                break;
+            }
+            cursor = page.last().cloned();
+            pages.push(page);
+        }
+        assert_eq!(pages.iter().map(|x| x.len()).collect_vec(), [3, 3, 1]);
+        assert_eq!(pages.concat(), TestObject::keys(&db)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_collection_iter() -> Result<()> {
+        let tdir = tempfile::tempdir()?;
+        let tmpdb = tdir.path().join("test.sqlite");
+        let db = DbConnection::connect(&format!("{}", tmpdb.display()))?;
+
+        for i in 0..10 {
+            TestObject { data: i as f64 }.put_into_collection(&db, &format!("x{}", i))?;
+        }
+        let items: Vec<_> = TestObject::iter(&db).batch_size(3).collect::<Result<_, _>>()?;
+        assert_eq!(items.len(), 10);
+        assert_eq!(items[9].0, "x9");
+        assert_eq!(items[9].1.data, 9.0);
+        // exactly a multiple of batch size
+        assert_eq!(TestObject::iter(&db).batch_size(5).count(), 10);
+        assert_eq!(TestObject::iter(&db).count(), 10);
+
+        // corrupted data
+        {
+            use crate::schema::kvstore::dsl::*;
+            let row = (
+                collection.eq(TestObject::collection_name()),
+                key.eq("x5a"),
+                data.eq(vec![1u8]),
+            );
+            diesel::insert_into(kvstore).values(&row).execute(&*db.writer())?;
+        }
+        let items: Vec<_> = TestObject::iter(&db).batch_size(4).collect();
+        assert_eq!(items.len(), 11);
+        assert!(matches!(items[6], Err(DbError::Deserialize { .. })));
+        assert!(items[7].is_ok());
+        let items: Vec<_> = TestObject::iter(&db)
+            .batch_size(4)
+            .skip_invalid()
+            .collect::<Result<_, _>>()?;
+        assert_eq!(items.len(), 10);
+
         Ok(())
     }
 }
@@ -0,0 +1,121 @@
+// [[file:../database.note::*compression][compression:1]]
+use crate::error::BoxedError;
+use crate::*;
+
+/// Compression of encoded data. The algorithm is recorded for each row, so
+/// data are always decompressed correctly regardless of current setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Compression {
+    /// Store data as is.
+    #[default]
+    None,
+    /// [zstd](https://facebook.github.io/zstd) with compression level (1-22,
+    /// 0 for the default level 3). Good ratio for large numeric arrays.
+    Zstd(i32),
+    /// [lz4](https://lz4.org) block format. Faster, but less compact than zstd.
+    Lz4,
+}
+
+impl Compression {
+    /// The identifier stored along with compressed data.
+    pub(crate) fn id(&self) -> i32 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd(_) => 1,
+            Compression::Lz4 => 2,
+        }
+    }
+
+    /// Return the compression for identifier `id`. The level of zstd is not
+    /// recorded, and reported as 0.
+    pub(crate) fn from_id(id: i32) -> Option<Self> {
+        let compression = match id {
+            0 => Compression::None,
+            1 => Compression::Zstd(0),
+            2 => Compression::Lz4,
+            _ => return None,
+        };
+        Some(compression)
+    }
+
+    /// Compress `data`. Return `None` if compression does not help.
+    pub(crate) fn compress(&self, data: &[u8]) -> Result<Option<Vec<u8>>, BoxedError> {
+        let compressed = match self {
+            Compression::None => return Ok(None),
+            Compression::Zstd(level) => zstd::bulk::compress(data, *level)?,
+            Compression::Lz4 => lz4_flex::compress_prepend_size(data),
+        };
+        if compressed.len() < data.len() {
+            Ok(Some(compressed))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Decompress `data` compressed with algorithm `id`.
+    pub(crate) fn decompress(id: i32, data: &[u8]) -> Result<Vec<u8>, BoxedError> {
+        let decompressed = match id {
+            0 => data.to_vec(),
+            1 => zstd::stream::decode_all(data)?,
+            2 => {
+                // lz4 expands data by 255 times at most, so a larger size
+                // prefix is corrupted.
+                let (size, block) = lz4_flex::block::uncompressed_size(data)?;
+                if size > block.len().saturating_mul(255) {
+                    return Err(format!("invalid lz4 uncompressed size: {}", size).into());
+                }
+                lz4_flex::decompress(block, size)?
+            }
+            _ => return Err(format!("unknown compression id: {}", id).into()),
+        };
+        Ok(decompressed)
+    }
+}
+
+impl FromStr for Compression {
+    type Err = Error;
+
+    /// Parse from "none", "lz4", "zstd", or "zstd:<level>".
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.to_lowercase();
+        let v = match s.split_once(':') {
+            Some(("zstd", level)) => Compression::Zstd(level.parse()?),
+            None if s == "zstd" => Compression::Zstd(0),
+            None if s == "lz4" => Compression::Lz4,
+            None if s == "none" => Compression::None,
+            _ => bail!("invalid compression: {}", s),
+        };
+        Ok(v)
+    }
+}
+// compression:1 ends here
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compression() -> Result<()> {
+        let data: Vec<u8> = (0..10000).map(|i| (i % 7) as u8).collect();
+        for c in [Compression::Zstd(0), Compression::Zstd(19), Compression::Lz4] {
+            let compressed = c.compress(&data).unwrap().expect("compressed");
+            assert!(compressed.len() < data.len());
+            let decompressed = Compression::decompress(c.id(), &compressed).unwrap();
+            assert_eq!(data, decompressed);
+        }
+        // corrupted size prefix
+        let mut compressed = Compression::Lz4.compress(&data).unwrap().expect("compressed");
+        compressed[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Compression::decompress(Compression::Lz4.id(), &compressed).is_err());
+
+        // not worth compressing
+        assert!(Compression::Zstd(0).compress(&[1]).unwrap().is_none());
+        assert!(Compression::None.compress(&data).unwrap().is_none());
+
+        assert_eq!("zstd:5".parse::<Compression>()?, Compression::Zstd(5));
+        assert_eq!("LZ4".parse::<Compression>()?, Compression::Lz4);
+        assert!("gzip".parse::<Compression>().is_err());
+
+        Ok(())
+    }
+}
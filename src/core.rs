@@ -57,7 +57,7 @@ pub fn save_model_results(mp: &ModelProperties, db: &DbConnection) -> Result<()>
     let mol = mp.get_molecule().expect("model properties has no structure!");
 
     // save molecule record
-    let conn = db.get();
+    let conn = db.writer();
 
     // insert a new properties record
     {
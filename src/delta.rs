@@ -0,0 +1,151 @@
+// [[file:../database.note::*delta][delta:1]]
+//! Binary diff of encoded data for incremental checkpoints.
+//!
+//! A diff holds the length of new data, followed by runs of changed bytes,
+//! each as the number of unchanged bytes before it, its length, and the new
+//! bytes. All numbers are LEB128 encoded. Bytes are compared at the same
+//! offset, which suits data of fixed layout such as numeric arrays.
+
+use crate::error::BoxedError;
+
+// unchanged bytes shorter than this are merged into the surrounding run,
+// which costs less than starting a new run.
+const MIN_GAP: usize = 8;
+
+/// Return the diff for rebuilding `new` from `base`.
+pub(crate) fn diff(base: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    write_varint(&mut out, new.len() as u64);
+
+    let n = base.len().min(new.len());
+    let same = |i: usize| i < n && base[i] == new[i];
+    // end of the last run
+    let mut pos = 0;
+    let mut i = 0;
+    loop {
+        while i < new.len() && same(i) {
+            i += 1;
+        }
+        if i >= new.len() {
+            break;
+        }
+        let start = i;
+        let mut end = i;
+        while i < new.len() && i - end < MIN_GAP {
+            if !same(i) {
+                end = i + 1;
+            }
+            i += 1;
+        }
+        write_varint(&mut out, (start - pos) as u64);
+        write_varint(&mut out, (end - start) as u64);
+        out.extend_from_slice(&new[start..end]);
+        pos = end;
+        i = end;
+    }
+    out
+}
+
+/// Rebuild new data from `base` and `diff` created by [`diff`].
+pub(crate) fn patch(base: &[u8], diff: &[u8]) -> Result<Vec<u8>, BoxedError> {
+    let mut diff = diff;
+    let len = read_varint(&mut diff)? as usize;
+    // the length is read from stored data, which may be corrupted
+    let mut out = Vec::with_capacity(len.min(base.len() + diff.len()));
+    let copy_base = |out: &mut Vec<u8>, n: usize| -> Result<(), BoxedError> {
+        if n == 0 {
+            return Ok(());
+        }
+        let p = out.len();
+        let unchanged = p
+            .checked_add(n)
+            .and_then(|end| base.get(p..end))
+            .ok_or("diff does not match base data")?;
+        out.extend_from_slice(unchanged);
+        Ok(())
+    };
+    while !diff.is_empty() {
+        let skip = read_varint(&mut diff)? as usize;
+        copy_base(&mut out, skip)?;
+        let n = read_varint(&mut diff)? as usize;
+        if n > diff.len() {
+            return Err("truncated diff".into());
+        }
+        out.extend_from_slice(&diff[..n]);
+        diff = &diff[n..];
+    }
+    if out.len() > len {
+        return Err("diff is longer than expected".into());
+    }
+    let rest = len - out.len();
+    copy_base(&mut out, rest)?;
+    Ok(out)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> Result<u64, BoxedError> {
+    let mut v = 0u64;
+    for (i, &b) in input.iter().enumerate() {
+        if i >= 10 {
+            break;
+        }
+        v |= ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            *input = &input[i + 1..];
+            return Ok(v);
+        }
+    }
+    Err("invalid varint in diff".into())
+}
+// delta:1 ends here
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_delta() {
+        let base: Vec<u8> = (0..10000).map(|i| (i % 251) as u8).collect();
+
+        let mut new = base.clone();
+        new[10] = 0;
+        new[15] = 0;
+        new[5000] = 0;
+        let d = diff(&base, &new);
+        assert!(d.len() < 30);
+        assert_eq!(patch(&base, &d).unwrap(), new);
+
+        // identical, growing, shrinking, and unrelated data
+        let cases = [
+            base.clone(),
+            [base.as_slice(), &[1, 2, 3]].concat(),
+            base[..5000].to_vec(),
+            vec![7; 300],
+            vec![],
+        ];
+        for new in cases {
+            let d = diff(&base, &new);
+            assert_eq!(patch(&base, &d).unwrap(), new);
+        }
+        assert_eq!(diff(&base, &base).len(), 2);
+
+        // wrong base
+        let d = diff(&base, &new);
+        assert!(patch(&base[..100], &d).is_err());
+        assert!(patch(&base, &d[..d.len() - 1]).is_err());
+
+        // corrupted length
+        let mut d = vec![];
+        write_varint(&mut d, u64::MAX);
+        assert!(patch(&base, &d).is_err());
+        write_varint(&mut d, usize::MAX as u64);
+        assert!(patch(&base, &d).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+// [[file:../database.note::*error][error:1]]
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+pub(crate) type BoxedError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Errors from database operations.
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    /// The requested checkpoint slot does not exist, including the case of no
+    /// checkpoint at all.
+    #[error("checkpoint slot {slot} is out of range: {len} checkpoints found with key {key}")]
+    SlotOutOfRange { key: String, slot: i32, len: usize },
+
+    /// No checkpoint with `key` matches `query`, e.g. a label or a tag.
+    #[error("no checkpoint with key {key} matches {query}")]
+    CheckpointNotFound { key: String, query: String },
+
+    /// The run to copy checkpoints into is not empty.
+    #[error("run {run} already has checkpoints")]
+    RunExists { run: String },
+
+    /// No item was found with `key`.
+    #[error("no item found with key {key}")]
+    KeyNotFound { key: String },
+
+    /// The stored data cannot be decoded as `type_name`.
+    #[error("failed to deserialize data for {key} as {type_name}")]
+    Deserialize {
+        key: String,
+        type_name: String,
+        #[source]
+        source: BoxedError,
+    },
+
+    /// The data of `type_name` cannot be encoded.
+    #[error("failed to serialize data of {type_name}")]
+    Serialize {
+        type_name: String,
+        #[source]
+        source: BoxedError,
+    },
+
+    /// Schema migrations failed.
+    #[error("failed to run database migrations")]
+    Migration(#[from] diesel_migrations::RunMigrationsError),
+
+    /// The database is locked by another connection for longer than the busy
+    /// timeout.
+    #[error("database is locked")]
+    Locked(#[source] DieselError),
+
+    #[error("failed to get a database connection from pool")]
+    Pool(#[from] diesel::r2d2::PoolError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// Other errors from the underlying database.
+    #[error(transparent)]
+    Database(DieselError),
+}
+
+impl DbError {
+    /// Return true if the error is caused by missing data, in contrast to
+    /// corrupted data or a failed database.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            DbError::SlotOutOfRange { .. } | DbError::CheckpointNotFound { .. } | DbError::KeyNotFound { .. }
+        )
+    }
+
+    pub(crate) fn serialize<T: ?Sized>(source: impl Into<BoxedError>) -> Self {
+        DbError::Serialize {
+            type_name: std::any::type_name::<T>().into(),
+            source: source.into(),
+        }
+    }
+
+    pub(crate) fn deserialize<T: ?Sized>(key: impl Into<String>, source: impl Into<BoxedError>) -> Self {
+        DbError::Deserialize {
+            key: key.into(),
+            type_name: std::any::type_name::<T>().into(),
+            source: source.into(),
+        }
+    }
+}
+
+impl From<DieselError> for DbError {
+    fn from(e: DieselError) -> Self {
+        match &e {
+            DieselError::DatabaseError(DatabaseErrorKind::__Unknown, info)
+                if info.message().contains("database is locked") || info.message().contains("database is busy") =>
+            {
+                DbError::Locked(e)
+            }
+            _ => DbError::Database(e),
+        }
+    }
+}
+// error:1 ends here
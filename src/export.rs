@@ -0,0 +1,171 @@
+// [[file:../database.note::*export][export:1]]
+//! Standalone files holding a single checkpoint.
+//!
+//! A file starts with the magic bytes, followed by the length of a JSON
+//! header as u32 in little endian, the header describing the checkpoint, and
+//! the stored data.
+
+use std::io::{Read, Write};
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+
+use crate::blob::Blob;
+use crate::checkpoint::{load_checkpoint_blob, load_checkpoint_info, load_checkpoint_info_n, Checkpoint, CheckpointDb};
+use crate::checkpoint::{save_checkpoint_meta, CheckpointInfo, CheckpointMeta};
+use crate::*;
+
+const MAGIC: &[u8; 8] = b"GOSHCKPT";
+const FORMAT_VERSION: u32 = 1;
+// far more than any header needs, to avoid huge allocation for a corrupted
+// or foreign file.
+const MAX_HEADER_LEN: u32 = 1 << 20;
+
+/// Everything about an exported checkpoint except its data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ExportHeader {
+    format: u32,
+    key: String,
+    codec: i32,
+    compression: i32,
+    version: i32,
+    ctime: NaiveDateTime,
+    mtime: NaiveDateTime,
+    meta: CheckpointMeta,
+}
+
+impl CheckpointDb {
+    /// Export the checkpoint of `T` in `slot` into a standalone file at
+    /// `path`, which can be imported into another checkpoint file with
+    /// [`import`](Self::import). Negative `slot` counts from the end.
+    pub fn export_slot<T: Checkpoint>(&self, slot: i32, path: impl AsRef<Path>) -> Result<CheckpointInfo> {
+        let path = path.as_ref();
+        let db = self
+            .db_connection
+            .as_ref()
+            .context("no checkpoint file to export from")?;
+        let conn = db.reader()?;
+        let ckpt = load_checkpoint_info_n(&conn, db.run(), &crate::registry::names_of::<T>(), slot)?;
+        // data stored as diff are rebuilt in full
+        let blob = load_checkpoint_blob(&conn, ckpt.id)?;
+
+        let header = ExportHeader {
+            format: FORMAT_VERSION,
+            key: T::checkpoint_name(),
+            codec: blob.codec,
+            compression: blob.compression,
+            version: blob.version,
+            ctime: ckpt.ctime,
+            mtime: ckpt.mtime,
+            meta: ckpt.meta.clone(),
+        };
+        let header = serde_json::to_vec(&header)?;
+        let mut f = std::fs::File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        f.write_all(MAGIC)?;
+        f.write_all(&(header.len() as u32).to_le_bytes())?;
+        f.write_all(&header)?;
+        f.write_all(&blob.data)?;
+        f.flush()?;
+        info!(
+            "exported checkpoint {} in slot {} to {}",
+            ckpt.key,
+            ckpt.slot,
+            path.display()
+        );
+
+        Ok(ckpt)
+    }
+
+    /// Import the checkpoint exported with [`export_slot`](Self::export_slot)
+    /// from file at `path`. The checkpoint is placed among existing ones with
+    /// the same key by its create time, and starts a new branch of history if
+    /// there are any. Sequence numbers of checkpoints created later are
+    /// shifted by one to make room for it. Return the summary of imported
+    /// checkpoint.
+    pub fn import(&self, path: impl AsRef<Path>) -> Result<CheckpointInfo> {
+        let path = path.as_ref();
+        let db = self
+            .db_connection
+            .as_ref()
+            .context("no checkpoint file to import into")?;
+        let (header, data) =
+            read_exported(path).with_context(|| format!("invalid checkpoint file {}", path.display()))?;
+        let blob = Blob {
+            data,
+            codec: header.codec,
+            compression: header.compression,
+            version: header.version,
+        };
+        let ckpt_id = insert_imported(db, &header, blob)?;
+
+        let conn = db.reader()?;
+        let ckpt = load_checkpoint_info(&conn, db.run(), std::slice::from_ref(&header.key), ckpt_id)?;
+        info!("imported checkpoint {} into slot {}", ckpt.key, ckpt.slot);
+        Ok(ckpt)
+    }
+}
+
+fn read_exported(path: &Path) -> Result<(ExportHeader, Vec<u8>)> {
+    let mut f = std::fs::File::open(path)?;
+    let mut magic = [0u8; 8];
+    f.read_exact(&mut magic)?;
+    ensure!(&magic == MAGIC, "not an exported checkpoint");
+    let mut n = [0u8; 4];
+    f.read_exact(&mut n)?;
+    let n = u32::from_le_bytes(n);
+    ensure!(n <= MAX_HEADER_LEN, "invalid header length {}", n);
+    let mut header = vec![0u8; n as usize];
+    f.read_exact(&mut header)?;
+    let header: ExportHeader = serde_json::from_slice(&header)?;
+    ensure!(
+        header.format <= FORMAT_VERSION,
+        "unsupported format version {}",
+        header.format
+    );
+    let mut data = vec![];
+    f.read_to_end(&mut data)?;
+    Ok((header, data))
+}
+
+/// Insert the checkpoint described by `header` with `blob`. Return its id.
+fn insert_imported(db: &DbConnection, header: &ExportHeader, blob: Blob) -> Result<i32, DbError> {
+    use crate::schema::checkpoints::dsl::*;
+
+    db.transaction(|tx| {
+        let conn = tx.writer();
+        let same_key = || checkpoints.filter(run.eq(tx.run())).filter(key.eq(&header.key));
+        // place after all created earlier
+        let before: Option<i64> = same_key()
+            .filter(ctime.le(header.ctime))
+            .select(diesel::dsl::max(seq))
+            .first(&*conn)?;
+        let new_seq = before.unwrap_or(0) + 1;
+        diesel::update(same_key().filter(seq.ge(new_seq)))
+            .set(seq.eq(seq + 1))
+            .execute(&*conn)?;
+        // unrelated to existing history
+        let last_branch: Option<i32> = same_key().select(diesel::dsl::max(branch)).first(&*conn)?;
+        let new_branch = last_branch.map_or(0, |b| b + 1);
+
+        let row = (
+            key.eq(&header.key),
+            run.eq(tx.run()),
+            seq.eq(new_seq),
+            data.eq(blob.data),
+            codec.eq(blob.codec),
+            compression.eq(blob.compression),
+            version.eq(blob.version),
+            ctime.eq(header.ctime),
+            mtime.eq(header.mtime),
+            label.eq(&header.meta.label),
+            step.eq(header.meta.step),
+            note.eq(&header.meta.note),
+            branch.eq(new_branch),
+        );
+        diesel::insert_into(checkpoints).values(&row).execute(&*conn)?;
+        let ckpt_id: i32 = checkpoints.select(id).order(id.desc()).first(&*conn)?;
+        save_checkpoint_meta(&conn, ckpt_id, &header.meta)?;
+        Ok(ckpt_id)
+    })
+}
+// export:1 ends here
@@ -0,0 +1,285 @@
+// [[file:../database.note::*group][group:1]]
+use crate::checkpoint::{
+    delete_checkpoints, insert_checkpoint, latest_checkpoint_id, load_checkpoint, slot_query, Checkpoint,
+    CheckpointMeta,
+};
+use crate::retention::RetentionItem;
+use crate::*;
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::NaiveDateTime;
+
+/// Checkpoints of different types committed together, so that they can be
+/// restored from the same moment.
+///
+/// # Example
+///
+/// ```no_run
+/// use gosh_database::{CheckpointGroup, DbConnection};
+///
+/// let db = DbConnection::connect("/tmp/test.sqlite").unwrap();
+/// let positions = vec![1.0, 2.0];
+/// let step = 10;
+///
+/// let group = CheckpointGroup::new("relax");
+/// group
+///     .commit(&db, |g| {
+///         g.add(&positions)?;
+///         g.add(&step)?;
+///         Ok(())
+///     })
+///     .unwrap();
+///
+/// let latest = group.load_n(&db, -1).unwrap();
+/// let positions: Vec<f64> = latest.get().unwrap();
+/// let step: i32 = latest.get().unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckpointGroup {
+    name: String,
+}
+
+/// Summary of a committed checkpoint group.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupInfo {
+    /// Index of the group among all with the same name (0-base).
+    pub slot: usize,
+    /// Row id in database.
+    pub id: i32,
+    /// Sequence number of the group, increasing with each commit for the
+    /// same name.
+    pub seq: i64,
+    /// The name of group.
+    pub name: String,
+    /// Create time in UTC.
+    pub ctime: NaiveDateTime,
+}
+
+/// Handle for adding checkpoints into a group being committed.
+pub struct GroupWriter<'a> {
+    db: &'a DbConnection,
+    group_id: i32,
+}
+
+/// A committed checkpoint group to restore from.
+#[derive(Debug, Clone)]
+pub struct GroupCheckpoint {
+    info: GroupInfo,
+    db: DbConnection,
+}
+
+impl CheckpointGroup {
+    /// Checkpoint group with `name`.
+    pub fn new(name: &str) -> Self {
+        Self { name: name.into() }
+    }
+
+    /// Return the name of group.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Commit checkpoints added in `f` as a new group in one transaction.
+    /// Nothing is committed if `f` fails.
+    pub fn commit<F>(&self, db: &DbConnection, f: F) -> Result<GroupInfo, DbError>
+    where
+        F: FnOnce(&mut GroupWriter) -> Result<(), DbError>,
+    {
+        use crate::schema::checkpoint_groups::dsl::*;
+
+        db.transaction(|tx| {
+            let conn = tx.writer();
+            let last: Option<i64> = checkpoint_groups
+                .filter(run.eq(tx.run()))
+                .filter(name.eq(&self.name))
+                .select(diesel::dsl::max(seq))
+                .first(&*conn)?;
+            let row = (
+                name.eq(&self.name),
+                run.eq(tx.run()),
+                seq.eq(last.unwrap_or(0) + 1),
+                ctime.eq(chrono::Utc::now().naive_utc()),
+            );
+            diesel::insert_into(checkpoint_groups).values(&row).execute(&*conn)?;
+            let group_id: i32 = checkpoint_groups.select(id).order(id.desc()).first(&*conn)?;
+
+            f(&mut GroupWriter { db: tx, group_id })?;
+
+            let infos = load_group_infos(&conn, tx.run(), &self.name)?;
+            Ok(infos.into_iter().last().expect("committed group"))
+        })
+    }
+
+    /// Return summaries of committed groups, ordered by slot.
+    pub fn list(&self, db: &DbConnection) -> Result<Vec<GroupInfo>, DbError> {
+        let conn = db.reader()?;
+        load_group_infos(&conn, db.run(), &self.name)
+    }
+
+    /// Delete groups not to be kept according to `policy`, together with all
+    /// their members. Groups are selected by commit sequence as checkpoints
+    /// in [`Checkpoint::prune_checkpoints`]. The latest group and groups
+    /// having a member with a label or tags are always kept. Return the
+    /// number of deleted groups.
+    pub fn prune(&self, db: &DbConnection, policy: RetentionPolicy) -> Result<usize, DbError> {
+        use crate::schema::checkpoints::dsl::*;
+        use diesel::dsl::sql;
+        use diesel::sql_types::Integer;
+
+        db.transaction(|tx| {
+            let conn = tx.writer();
+            let groups = load_group_infos(&conn, tx.run(), &self.name)?;
+            let group_ids = groups.iter().map(|x| x.id).collect_vec();
+            let members: Vec<(i32, Option<i32>, i32, Option<String>)> = checkpoints
+                .filter(group_id.eq_any(&group_ids))
+                .select((id, group_id, sql::<Integer>("length(data)"), label))
+                .load(&*conn)?;
+            let tagged: HashSet<i32> = {
+                use crate::schema::checkpoint_tags::dsl::*;
+                let member_ids = members.iter().map(|x| x.0).collect_vec();
+                checkpoint_tags
+                    .filter(checkpoint_id.eq_any(&member_ids))
+                    .select(checkpoint_id)
+                    .load::<i32>(&*conn)?
+                    .into_iter()
+                    .collect()
+            };
+
+            let mut items = groups
+                .iter()
+                .map(|x| RetentionItem {
+                    seq: x.seq,
+                    size: 0,
+                    pinned: false,
+                })
+                .collect_vec();
+            let slots: HashMap<i32, usize> = groups.iter().map(|x| (x.id, x.slot)).collect();
+            for (ckpt_id, ckpt_group, ckpt_size, ckpt_label) in &members {
+                if let Some(&i) = ckpt_group.and_then(|g| slots.get(&g)) {
+                    items[i].size += *ckpt_size as u64;
+                    items[i].pinned |= ckpt_label.is_some() || tagged.contains(ckpt_id);
+                }
+            }
+            if let Some(latest) = items.last_mut() {
+                latest.pinned = true;
+            }
+
+            let pruned: HashSet<i32> = policy
+                .select_dropped(&items)
+                .into_iter()
+                .map(|i| groups[i].id)
+                .collect();
+            if !pruned.is_empty() {
+                info!("Prune {} of {} groups {}", pruned.len(), groups.len(), self.name);
+            }
+            let ids = members
+                .iter()
+                .filter(|x| x.1.is_some_and(|g| pruned.contains(&g)))
+                .map(|x| x.0)
+                .collect_vec();
+            delete_checkpoints(tx, &ids)?;
+            // groups without any member
+            {
+                use crate::schema::checkpoint_groups::dsl::*;
+                diesel::delete(checkpoint_groups.filter(id.eq_any(&pruned))).execute(&*conn)?;
+            }
+            Ok(pruned.len())
+        })
+    }
+
+    /// Load the group committed in slot `n` (ordered by commit sequence).
+    /// Negative `n` counts from the end, e.g. -1 for the latest.
+    pub fn load_n(&self, db: &DbConnection, n: i32) -> Result<GroupCheckpoint, DbError> {
+        let conn = db.reader()?;
+        let mut infos = load_group_infos(&conn, db.run(), &self.name)?;
+        let ngroups = infos.len();
+        let k = if n < 0 { ngroups as i32 + n } else { n };
+        if k < 0 || k as usize >= ngroups {
+            return Err(DbError::SlotOutOfRange {
+                key: self.name.clone(),
+                slot: n,
+                len: ngroups,
+            });
+        }
+        Ok(GroupCheckpoint {
+            info: infos.swap_remove(k as usize),
+            db: db.clone(),
+        })
+    }
+}
+
+impl<'a> GroupWriter<'a> {
+    /// Add `value` into the group.
+    pub fn add<T: Checkpoint>(&mut self, value: &T) -> Result<(), DbError> {
+        self.add_with(value, &CheckpointMeta::default())
+    }
+
+    /// Add `value` with metadata `meta` into the group.
+    pub fn add_with<T: Checkpoint>(&mut self, value: &T, meta: &CheckpointMeta) -> Result<(), DbError> {
+        insert_checkpoint(self.db, value, meta, Some(self.group_id))?;
+        Ok(())
+    }
+}
+
+impl GroupCheckpoint {
+    /// Return the summary of the group.
+    pub fn info(&self) -> &GroupInfo {
+        &self.info
+    }
+
+    /// Load `T` committed in the group.
+    pub fn get<T: Checkpoint>(&self) -> Result<T, DbError> {
+        let (x, _) = self.load()?;
+        Ok(x)
+    }
+
+    /// Restore `value` from the checkpoint of its type in the group. Later
+    /// checkpoints of `T` continue from it.
+    pub fn restore<T: Checkpoint>(&self, value: &mut T) -> Result<(), DbError> {
+        let (x, ckpt_id) = self.load()?;
+        value.clone_from(&x);
+        self.db.set_checkpoint_head(&T::checkpoint_name(), ckpt_id);
+        Ok(())
+    }
+
+    /// Load `T` committed in the group, together with the checkpoint id.
+    fn load<T: Checkpoint>(&self) -> Result<(T, i32), DbError> {
+        let conn = self.db.reader()?;
+        let ckpt_key = T::checkpoint_name();
+        let keys = crate::registry::names_of::<T>();
+        let query = format!("group={}/{}", self.info.name, self.info.seq);
+        let in_group = slot_query(self.db.run(), &keys).filter(crate::schema::checkpoints::group_id.eq(self.info.id));
+        let ckpt_id = latest_checkpoint_id(&conn, in_group, &keys)?.ok_or_else(|| DbError::CheckpointNotFound {
+            key: ckpt_key.clone(),
+            query: query.clone(),
+        })?;
+        let x = load_checkpoint(&conn, ckpt_id, &format!("{}/{}", ckpt_key, query))?;
+        Ok((x, ckpt_id))
+    }
+}
+
+/// Load summaries of groups in `group_run` with `group_name`, ordered by slot.
+fn load_group_infos(conn: &SqliteConnection, group_run: &str, group_name: &str) -> Result<Vec<GroupInfo>, DbError> {
+    use crate::schema::checkpoint_groups::dsl::*;
+
+    let rows: Vec<(i32, i64, String, NaiveDateTime)> = checkpoint_groups
+        .filter(run.eq(group_run))
+        .filter(name.eq(group_name))
+        .select((id, seq, name, ctime))
+        .order(seq.asc())
+        .load(conn)?;
+    let infos = rows
+        .into_iter()
+        .enumerate()
+        .map(|(slot, (group_id, group_seq, group_name, group_ctime))| GroupInfo {
+            slot,
+            id: group_id,
+            seq: group_seq,
+            name: group_name,
+            ctime: group_ctime,
+        })
+        .collect();
+    Ok(infos)
+}
+// group:1 ends here
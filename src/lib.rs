@@ -1,21 +1,43 @@
 // [[file:../database.note::*imports][imports:1]]
+// impls generated by diesel 1.x macros are not at the module level
+#![allow(non_local_definitions)]
+
 #[macro_use]
 extern crate diesel;
 #[macro_use]
-extern crate serde;
-#[macro_use]
 extern crate diesel_migrations;
 #[macro_use]
 extern crate derivative;
 
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::{ReentrantMutex, ReentrantMutexGuard};
 
 use diesel::prelude::*;
 // imports:1 ends here
 
 // [[file:../database.note::*mods][mods:1]]
+mod auto;
+mod blob;
 mod checkpoint;
 mod collection;
+mod compression;
+mod delta;
+mod error;
+mod export;
+mod group;
+mod options;
+mod registry;
+mod retention;
+mod run;
+#[cfg(feature = "signal")]
+mod signal;
+mod writer;
+
+pub mod codec;
+// NOTE: model results storage is not wired up yet
+#[allow(dead_code)]
 mod core;
 
 pub(crate) mod schema;
@@ -25,73 +47,365 @@ pub(crate) mod schema;
 use gosh_core::*;
 use gut::prelude::*;
 
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, Pool, PooledConnection};
+
 embed_migrations!();
 
+type SqlitePool = Pool<ConnectionManager<SqliteConnection>>;
+
 #[derive(Clone, Derivative)]
 #[derivative(Debug)]
 pub struct DbConnection {
     database_url: String,
+    options: DbOptions,
+    // all writes are serialized through this connection. The lock is
+    // reentrant so that nested calls inside a transaction on the same thread
+    // will share the same connection.
+    #[derivative(Debug = "ignore")]
+    writer: Arc<ReentrantMutex<SqliteConnection>>,
+    // pooled connections for concurrent reads. None for in-memory database,
+    // for which each new connection would open a different database, or
+    // inside a transaction, for which reads must see uncommitted writes.
     #[derivative(Debug = "ignore")]
-    connection: Arc<Mutex<SqliteConnection>>,
+    readers: Option<SqlitePool>,
+    // namespace of checkpoints, see `with_run`
+    run: String,
+    // the checkpoint last restored from or committed in this session, keyed
+    // by run and checkpoint name. New checkpoints continue from it.
+    heads: Arc<parking_lot::Mutex<HashMap<HeadKey, i32>>>,
+    // changes of heads in each level of enclosing transactions, from the
+    // outermost. They are applied to `heads` when the outermost commits.
+    #[derivative(Debug = "ignore")]
+    staged_heads: Vec<Arc<parking_lot::Mutex<Vec<HeadChange>>>>,
+}
+
+// run and checkpoint name
+type HeadKey = (String, String);
+
+/// A change of checkpoint heads made in a transaction.
+#[derive(Debug, Clone)]
+enum HeadChange {
+    Set(HeadKey, i32),
+    Drop(Vec<i32>),
+}
+
+impl HeadChange {
+    /// Return the head of `k` after this change, given the one before.
+    fn apply_to(&self, k: &HeadKey, head: Option<i32>) -> Option<i32> {
+        match self {
+            HeadChange::Set(key, ckpt_id) if key == k => Some(*ckpt_id),
+            HeadChange::Drop(ids) if head.is_some_and(|x| ids.contains(&x)) => None,
+            _ => head,
+        }
+    }
+
+    fn apply(self, heads: &mut HashMap<HeadKey, i32>) {
+        match self {
+            HeadChange::Set(k, ckpt_id) => {
+                heads.insert(k, ckpt_id);
+            }
+            HeadChange::Drop(ids) => heads.retain(|_, head| !ids.contains(head)),
+        }
+    }
+}
+
+/// Connection for read-only queries, either checked out from the reader pool
+/// or borrowed from the writer.
+pub(crate) enum ReadConnection<'a> {
+    Pooled(PooledConnection<ConnectionManager<SqliteConnection>>),
+    Writer(ReentrantMutexGuard<'a, SqliteConnection>),
+}
+
+impl<'a> std::ops::Deref for ReadConnection<'a> {
+    type Target = SqliteConnection;
+
+    fn deref(&self) -> &SqliteConnection {
+        match self {
+            ReadConnection::Pooled(conn) => conn,
+            ReadConnection::Writer(conn) => conn,
+        }
+    }
+}
+
+// Setup for connections checked out from the reader pool.
+#[derive(Debug)]
+struct ReaderCustomizer(DbOptions);
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for ReaderCustomizer {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> std::result::Result<(), diesel::r2d2::Error> {
+        self.0.apply(conn).map_err(diesel::r2d2::Error::QueryError)?;
+        // guard against accidental writes through a reader
+        conn.execute("PRAGMA query_only = ON")
+            .map_err(diesel::r2d2::Error::QueryError)?;
+        Ok(())
+    }
+}
+
+fn is_memory_database(database_url: &str) -> bool {
+    database_url.is_empty() || database_url == ":memory:" || database_url.contains("mode=memory")
 }
 
 impl DbConnection {
     /// Eastablish connection to database specified using env var
-    /// `GOSH_DATABASE_URL`.
+    /// `GOSH_DATABASE_URL`. Connection options are read from env vars as
+    /// well, see [`DbOptions::from_env`].
     pub fn establish() -> Result<DbConnection> {
         // read vars from .env file
         dotenv::dotenv().ok();
 
-        let database_url =
-            std::env::var("GOSH_DATABASE_URL").with_context(|| format!("GOSH_DATABASE_URL var not set"))?;
+        let database_url = std::env::var("GOSH_DATABASE_URL").context("GOSH_DATABASE_URL var not set")?;
         debug!("Database: {}", database_url);
 
         Self::connect(&database_url)
     }
 
-    /// Connect to database specified using `database_url`.
+    /// Connect to database specified using `database_url`. Connection
+    /// options are read from env vars, see [`DbOptions::from_env`].
     pub fn connect(database_url: &str) -> Result<DbConnection> {
-        // diesel accept &str, not Path
-        let conn = SqliteConnection::establish(database_url)?;
+        let options = DbOptions::from_env()?;
+        Self::connect_with(database_url, &options)
+    }
 
-        // see: https://sqlite.org/faq.html#q19
-        //
-        // With synchronous OFF, SQLite continues without syncing as soon as
-        // it has handed data off to the operating system. If the application
-        // running SQLite crashes, the data will be safe, but the database might
-        // become corrupted if the operating system crashes or the computer
-        // loses power before that data has been written to the disk surface. On
-        // the other hand, commits can be orders of magnitude faster with
-        // synchronous OFF.
-        conn.execute("PRAGMA synchronous = OFF")?;
+    /// Connect to database specified using `database_url`, with at most
+    /// `pool_size` connections for concurrent reads. With `pool_size` being
+    /// 0, reads will share the single writer connection.
+    pub fn connect_with_pool_size(database_url: &str, pool_size: u32) -> Result<DbConnection> {
+        let options = DbOptions::from_env()?.pool_size(pool_size);
+        Self::connect_with(database_url, &options)
+    }
 
-        let conn = Arc::new(Mutex::new(conn));
+    /// Connect to database specified using `database_url` with `options`.
+    pub fn connect_with(database_url: &str, options: &DbOptions) -> Result<DbConnection> {
+        // diesel accept &str, not Path
+        let conn = SqliteConnection::establish(database_url)?;
+        options
+            .apply_to_writer(&conn)
+            .with_context(|| format!("failed to set up connection to {}", database_url))?;
 
-        let db = DbConnection {
+        let mut db = DbConnection {
             database_url: database_url.into(),
-            connection: conn.clone(),
+            options: options.clone(),
+            writer: Arc::new(ReentrantMutex::new(conn)),
+            readers: None,
+            run: String::new(),
+            heads: Default::default(),
+            staged_heads: vec![],
         };
 
+        // create tables before any reader could see the database
         db.migrate()?;
 
+        let pool_size = options.get_pool_size();
+        if pool_size > 0 && !is_memory_database(database_url) {
+            let manager = ConnectionManager::<SqliteConnection>::new(database_url);
+            let pool = Pool::builder()
+                .max_size(pool_size)
+                .min_idle(Some(1))
+                .connection_customizer(Box::new(ReaderCustomizer(options.clone())))
+                .build(manager)
+                .with_context(|| format!("failed to create connection pool for {}", database_url))?;
+            db.readers = Some(pool);
+        }
+
         Ok(db)
     }
 
+    /// Return the options used for connecting.
+    pub fn options(&self) -> &DbOptions {
+        &self.options
+    }
+
     /// Show database url.
     pub fn database_url(&self) -> &str {
         &self.database_url
     }
 
-    pub(crate) fn get(&self) -> MutexGuard<'_, SqliteConnection> {
-        self.connection.lock().expect("cannot lock db connection!")
+    /// Return a connection to the same database, for checkpoints in `run`.
+    /// Runs separate histories of different jobs sharing one file, and slots
+    /// are counted within each run. The default run is named by the empty
+    /// string.
+    pub fn with_run(&self, run: &str) -> DbConnection {
+        DbConnection {
+            run: run.into(),
+            ..self.clone()
+        }
+    }
+
+    /// Return the run of checkpoints accessed through this connection.
+    pub fn run(&self) -> &str {
+        &self.run
+    }
+
+    /// Return the max number of pooled connections for reads.
+    pub fn pool_size(&self) -> u32 {
+        self.readers.as_ref().map_or(0, |pool| pool.max_size())
+    }
+
+    /// Return the connection for writes. Writes are serialized, so this will
+    /// block if another thread is writing.
+    pub(crate) fn writer(&self) -> ReentrantMutexGuard<'_, SqliteConnection> {
+        self.writer.lock()
+    }
+
+    /// Return a connection for reads. Reads from different threads can run
+    /// concurrently.
+    pub(crate) fn reader(&self) -> Result<ReadConnection<'_>, DbError> {
+        match &self.readers {
+            Some(pool) => Ok(ReadConnection::Pooled(pool.get()?)),
+            None => Ok(ReadConnection::Writer(self.writer())),
+        }
+    }
+
+    /// Return the id of checkpoint last restored from or committed with `key`
+    /// in the run of this session.
+    pub(crate) fn checkpoint_head(&self, key: &str) -> Option<i32> {
+        let k = (self.run.clone(), key.into());
+        let mut head = self.heads.lock().get(&k).copied();
+        for staged in &self.staged_heads {
+            for change in staged.lock().iter() {
+                head = change.apply_to(&k, head);
+            }
+        }
+        head
+    }
+
+    pub(crate) fn set_checkpoint_head(&self, key: &str, ckpt_id: i32) {
+        self.change_checkpoint_heads(HeadChange::Set((self.run.clone(), key.into()), ckpt_id));
+    }
+
+    /// Forget heads pointing at checkpoints `ids` in any run, which are
+    /// deleted.
+    pub(crate) fn drop_checkpoint_heads(&self, ids: &[i32]) {
+        self.change_checkpoint_heads(HeadChange::Drop(ids.to_vec()));
+    }
+
+    // staged until the transaction commits, so that rolled back changes in
+    // database leave heads untouched.
+    fn change_checkpoint_heads(&self, change: HeadChange) {
+        match self.staged_heads.last() {
+            Some(staged) => staged.lock().push(change),
+            None => change.apply(&mut self.heads.lock()),
+        }
+    }
+
+    /// Run `f` in a transaction. All writes through the handle passed to
+    /// `f` will be committed if `f` returns `Ok`, or rolled back otherwise.
+    ///
+    /// The handle can be passed to any method of `Checkpoint` or
+    /// `Collection`. Reads through the handle will see uncommitted writes.
+    /// Calling `transaction` on the handle again creates a savepoint, which
+    /// can be rolled back without aborting the outer transaction.
+    ///
+    /// Writes from other threads will be blocked until the transaction ends,
+    /// so do not wait on them inside `f`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use gosh_database::prelude::*;
+    /// use gosh_database::DbConnection;
+    ///
+    /// let db = DbConnection::connect("/tmp/test.sqlite").unwrap();
+    /// let x = vec![1.0, 2.0];
+    /// db.transaction(|tx| {
+    ///     x.commit_checkpoint(tx)?;
+    ///     x.put_into_collection(tx, "x")?;
+    ///     Ok::<_, gosh_core::gut::prelude::Error>(())
+    /// })
+    /// .unwrap();
+    /// ```
+    pub fn transaction<T, E, F>(&self, f: F) -> std::result::Result<T, E>
+    where
+        F: FnOnce(&DbConnection) -> std::result::Result<T, E>,
+        E: From<diesel::result::Error>,
+    {
+        use diesel::connection::TransactionManager;
+
+        let conn = self.writer();
+        // route all reads through the locked writer
+        let mut tx = DbConnection {
+            readers: None,
+            ..self.clone()
+        };
+        let staged = Arc::new(parking_lot::Mutex::new(vec![]));
+        tx.staged_heads.push(staged.clone());
+        // Take the write lock up front, so that concurrent writers from other
+        // processes wait for the busy timeout instead of failing on upgrading
+        // a read lock. Nested transactions become savepoints.
+        let depth = TransactionManager::<SqliteConnection>::get_transaction_depth(conn.transaction_manager());
+        let result = if depth == 0 {
+            conn.immediate_transaction(|| f(&tx))
+        } else {
+            conn.transaction(|| f(&tx))
+        };
+        if result.is_ok() {
+            for change in std::mem::take(&mut *staged.lock()) {
+                self.change_checkpoint_heads(change);
+            }
+        }
+        result
+    }
+
+    /// Rewrite stored data under name `old` to name `new` in place, e.g.
+    /// after renaming a type. Checkpoints under `old` are placed before those
+    /// under `new`. Items in collection `old` with the same key as in
+    /// collection `new` are discarded. Return the number of rows rewritten.
+    pub fn rename_key(&self, old: &str, new: &str) -> Result<usize, DbError> {
+        use diesel::sql_types::Text;
+
+        self.transaction(|tx| {
+            let conn = tx.writer();
+            // make room for old checkpoints in sequence of each run
+            diesel::sql_query(
+                "UPDATE checkpoints SET seq = seq + (
+                   SELECT COALESCE(MAX(c.seq), 0) FROM checkpoints c WHERE c.key = ? AND c.run = checkpoints.run)
+                 WHERE key = ?",
+            )
+            .bind::<Text, _>(old)
+            .bind::<Text, _>(new)
+            .execute(&*conn)?;
+            let n = diesel::sql_query("UPDATE checkpoints SET key = ? WHERE key = ?")
+                .bind::<Text, _>(new)
+                .bind::<Text, _>(old)
+                .execute(&*conn)?;
+            let m = diesel::sql_query("UPDATE OR IGNORE kvstore SET collection = ? WHERE collection = ?")
+                .bind::<Text, _>(new)
+                .bind::<Text, _>(old)
+                .execute(&*conn)?;
+            diesel::sql_query("DELETE FROM kvstore WHERE collection = ?")
+                .bind::<Text, _>(old)
+                .execute(&*conn)?;
+            Ok(n + m)
+        })
+    }
+
+    /// Rewrite stored data of `T` under registered aliases to its current
+    /// name. See [`Register::alias`].
+    pub fn rename_aliases<T: ?Sized>(&self) -> Result<usize, DbError> {
+        let names = crate::registry::names_of::<T>();
+        self.transaction(|tx| {
+            let mut n = 0;
+            for alias in &names[1..] {
+                n += tx.rename_key(alias, &names[0])?;
+            }
+            Ok(n)
+        })
+    }
+
+    /// Rebuild the database file to reclaim space of deleted data, e.g. after
+    /// pruning checkpoints. This cannot be called inside a transaction.
+    pub fn vacuum(&self) -> Result<(), DbError> {
+        let conn = self.writer();
+        conn.execute("VACUUM")?;
+        Ok(())
     }
 
     // for schema migrations, sql tables initialization
     fn migrate(&self) -> Result<()> {
-        let conn = self.get();
+        let conn = self.writer();
 
         // This will run the necessary migrations.
-        embedded_migrations::run(&*conn)?;
+        embedded_migrations::run(&*conn).map_err(DbError::Migration)?;
 
         Ok(())
     }
@@ -104,5 +418,16 @@ pub mod prelude {
     pub use crate::collection::Collection;
 }
 
-pub use crate::checkpoint::CheckpointDb;
+pub use crate::auto::AutoCheckpoint;
+pub use crate::checkpoint::{BranchInfo, CheckpointDb, CheckpointInfo, CheckpointMeta, RestoreOutcome, ResumePolicy};
+pub use crate::collection::CollectionIter;
+pub use crate::compression::Compression;
+pub use crate::error::DbError;
+pub use crate::group::{CheckpointGroup, GroupCheckpoint, GroupInfo, GroupWriter};
+pub use crate::options::{DbOptions, JournalMode, Synchronous};
+pub use crate::registry::{register, Named, Register, Versioned};
+pub use crate::retention::RetentionPolicy;
+#[cfg(feature = "signal")]
+pub use crate::signal::SignalGuard;
+pub use crate::writer::CheckpointWriter;
 // exports:1 ends here
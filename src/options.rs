@@ -0,0 +1,339 @@
+// [[file:../database.note::*options][options:1]]
+use crate::*;
+
+use crate::compression::Compression;
+
+/// The `PRAGMA synchronous` setting. See: https://sqlite.org/pragma.html#pragma_synchronous
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Synchronous {
+    /// Hand data off to the operating system without syncing. Fastest, but
+    /// the database may be corrupted on power loss.
+    Off,
+    /// Sync at the most critical moments. Safe from corruption in WAL mode.
+    Normal,
+    /// Sync after each transaction. Safe from corruption on power loss.
+    Full,
+    /// Like `Full`, and also sync the directory of rollback journal.
+    Extra,
+}
+
+impl Synchronous {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+}
+
+impl FromStr for Synchronous {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let v = match s.to_lowercase().as_str() {
+            "off" | "0" => Synchronous::Off,
+            "normal" | "1" => Synchronous::Normal,
+            "full" | "2" => Synchronous::Full,
+            "extra" | "3" => Synchronous::Extra,
+            _ => bail!("invalid synchronous level: {}", s),
+        };
+        Ok(v)
+    }
+}
+
+/// The `PRAGMA journal_mode` setting. See: https://sqlite.org/pragma.html#pragma_journal_mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalMode {
+    /// Rollback journal, deleted at the end of each transaction (the SQLite
+    /// default).
+    Delete,
+    /// Rollback journal, truncated to zero length at the end of each
+    /// transaction.
+    Truncate,
+    /// Rollback journal, header zeroed at the end of each transaction.
+    Persist,
+    /// Rollback journal kept in memory.
+    Memory,
+    /// Write-ahead log. Readers never block the writer, but it does not work
+    /// over network filesystems.
+    Wal,
+}
+
+impl JournalMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Memory => "MEMORY",
+            JournalMode::Wal => "WAL",
+        }
+    }
+}
+
+impl FromStr for JournalMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let v = match s.to_lowercase().as_str() {
+            "delete" => JournalMode::Delete,
+            "truncate" => JournalMode::Truncate,
+            "persist" => JournalMode::Persist,
+            "memory" => JournalMode::Memory,
+            "wal" => JournalMode::Wal,
+            _ => bail!("invalid journal mode: {}", s),
+        };
+        Ok(v)
+    }
+}
+
+/// Options for connecting to database.
+///
+/// # Example
+///
+/// ```no_run
+/// use gosh_database::{DbOptions, JournalMode, Synchronous};
+///
+/// let db = DbOptions::safe()
+///     .journal_mode(JournalMode::Wal)
+///     .synchronous(Synchronous::Normal)
+///     .connect("/tmp/test.sqlite")
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct DbOptions {
+    synchronous: Synchronous,
+    journal_mode: Option<JournalMode>,
+    busy_timeout: u32,
+    cache_size: Option<i64>,
+    foreign_keys: bool,
+    pool_size: u32,
+    compression: Compression,
+}
+
+impl Default for DbOptions {
+    /// Turn off synchronous as before, and leave others as SQLite defaults.
+    fn default() -> Self {
+        Self {
+            synchronous: Synchronous::Off,
+            journal_mode: None,
+            busy_timeout: 5000,
+            cache_size: None,
+            foreign_keys: false,
+            pool_size: default_pool_size(),
+            compression: Compression::None,
+        }
+    }
+}
+
+/// The default number of pooled read connections.
+fn default_pool_size() -> u32 {
+    std::thread::available_parallelism()
+        .map(|n| n.get() as u32)
+        .unwrap_or(4)
+}
+
+fn parse_bool(s: &str) -> Result<bool> {
+    let v = match s.to_lowercase().as_str() {
+        "1" | "on" | "yes" | "true" => true,
+        "0" | "off" | "no" | "false" => false,
+        _ => bail!("invalid boolean value: {}", s),
+    };
+    Ok(v)
+}
+
+/// Parse env var `name` if it is set.
+fn parse_env<T>(name: &str, parse: impl Fn(&str) -> Result<T>) -> Result<Option<T>> {
+    match std::env::var(name) {
+        Ok(s) => {
+            let v = parse(&s).with_context(|| format!("invalid value for {}: {}", name, s))?;
+            Ok(Some(v))
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// Presets
+impl DbOptions {
+    /// Options with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Settings for durability: data survive a power loss on the cost of
+    /// slower commits. Rollback journal is used, which also works on network
+    /// filesystems.
+    pub fn safe() -> Self {
+        Self {
+            synchronous: Synchronous::Full,
+            journal_mode: Some(JournalMode::Delete),
+            foreign_keys: true,
+            ..Self::default()
+        }
+    }
+
+    /// Settings for speed: commits are not synced to disk, and the database
+    /// might be corrupted if the operating system crashes.
+    pub fn fast() -> Self {
+        Self {
+            synchronous: Synchronous::Off,
+            journal_mode: Some(JournalMode::Wal),
+            cache_size: Some(-64000),
+            ..Self::default()
+        }
+    }
+
+    /// Read options from env vars. Unset vars are left as default.
+    ///
+    /// | env var                      | example             |
+    /// |------------------------------|---------------------|
+    /// | `GOSH_DATABASE_PRESET`       | safe, fast          |
+    /// | `GOSH_DATABASE_SYNCHRONOUS`  | off, normal, full   |
+    /// | `GOSH_DATABASE_JOURNAL_MODE` | wal, delete         |
+    /// | `GOSH_DATABASE_BUSY_TIMEOUT` | 5000 (ms)           |
+    /// | `GOSH_DATABASE_CACHE_SIZE`   | -64000 (KiB if < 0) |
+    /// | `GOSH_DATABASE_FOREIGN_KEYS` | on, off             |
+    /// | `GOSH_DATABASE_POOL_SIZE`    | 8                   |
+    /// | `GOSH_DATABASE_COMPRESSION`  | none, lz4, zstd:3   |
+    pub fn from_env() -> Result<Self> {
+        let preset = parse_env("GOSH_DATABASE_PRESET", |s| match s.to_lowercase().as_str() {
+            "safe" => Ok(Self::safe()),
+            "fast" => Ok(Self::fast()),
+            "default" => Ok(Self::default()),
+            _ => bail!("unknown preset"),
+        })?;
+        let mut opts = preset.unwrap_or_default();
+
+        if let Some(v) = parse_env("GOSH_DATABASE_SYNCHRONOUS", |s| s.parse())? {
+            opts.synchronous = v;
+        }
+        if let Some(v) = parse_env("GOSH_DATABASE_JOURNAL_MODE", |s| s.parse())? {
+            opts.journal_mode = Some(v);
+        }
+        if let Some(v) = parse_env("GOSH_DATABASE_BUSY_TIMEOUT", |s| Ok(s.parse()?))? {
+            opts.busy_timeout = v;
+        }
+        if let Some(v) = parse_env("GOSH_DATABASE_CACHE_SIZE", |s| Ok(s.parse()?))? {
+            opts.cache_size = Some(v);
+        }
+        if let Some(v) = parse_env("GOSH_DATABASE_FOREIGN_KEYS", parse_bool)? {
+            opts.foreign_keys = v;
+        }
+        if let Some(v) = parse_env("GOSH_DATABASE_POOL_SIZE", |s| Ok(s.parse()?))? {
+            opts.pool_size = v;
+        }
+        if let Some(v) = parse_env("GOSH_DATABASE_COMPRESSION", |s| s.parse())? {
+            opts.compression = v;
+        }
+
+        Ok(opts)
+    }
+}
+
+/// Builder
+impl DbOptions {
+    /// Set `PRAGMA synchronous`.
+    pub fn synchronous(mut self, level: Synchronous) -> Self {
+        self.synchronous = level;
+        self
+    }
+
+    /// Set `PRAGMA journal_mode`.
+    pub fn journal_mode(mut self, mode: JournalMode) -> Self {
+        self.journal_mode = Some(mode);
+        self
+    }
+
+    /// Set how long to wait in milliseconds when the database is locked by
+    /// another connection.
+    pub fn busy_timeout(mut self, ms: u32) -> Self {
+        self.busy_timeout = ms;
+        self
+    }
+
+    /// Set `PRAGMA cache_size`. A positive value is in pages, and a negative
+    /// value is in KiB.
+    pub fn cache_size(mut self, size: i64) -> Self {
+        self.cache_size = Some(size);
+        self
+    }
+
+    /// Enable or disable foreign key constraints.
+    pub fn foreign_keys(mut self, enabled: bool) -> Self {
+        self.foreign_keys = enabled;
+        self
+    }
+
+    /// Set the max number of pooled connections for concurrent reads.
+    pub fn pool_size(mut self, n: u32) -> Self {
+        self.pool_size = n;
+        self
+    }
+
+    /// Set the default compression for stored data, which can be overridden
+    /// for each type by [`Register::compression`](crate::Register::compression).
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Connect to database specified using `database_url` with these
+    /// options.
+    pub fn connect(&self, database_url: &str) -> Result<DbConnection> {
+        DbConnection::connect_with(database_url, self)
+    }
+}
+
+impl DbOptions {
+    pub(crate) fn get_pool_size(&self) -> u32 {
+        self.pool_size
+    }
+
+    pub(crate) fn get_compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Apply per-connection settings on `conn`.
+    pub(crate) fn apply(&self, conn: &SqliteConnection) -> QueryResult<()> {
+        conn.execute(&format!("PRAGMA busy_timeout = {}", self.busy_timeout))?;
+        conn.execute(&format!("PRAGMA synchronous = {}", self.synchronous.as_str()))?;
+        if let Some(n) = self.cache_size {
+            conn.execute(&format!("PRAGMA cache_size = {}", n))?;
+        }
+        let fk = if self.foreign_keys { "ON" } else { "OFF" };
+        conn.execute(&format!("PRAGMA foreign_keys = {}", fk))?;
+        Ok(())
+    }
+
+    /// Apply database wide settings on the writer connection `conn`.
+    pub(crate) fn apply_to_writer(&self, conn: &SqliteConnection) -> QueryResult<()> {
+        if let Some(mode) = self.journal_mode {
+            conn.execute(&format!("PRAGMA journal_mode = {}", mode.as_str()))?;
+        }
+        self.apply(conn)
+    }
+}
+// options:1 ends here
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_db_options() -> Result<()> {
+        assert_eq!("WAL".parse::<JournalMode>()?, JournalMode::Wal);
+        assert_eq!("normal".parse::<Synchronous>()?, Synchronous::Normal);
+        assert!("wall".parse::<JournalMode>().is_err());
+
+        let tdir = tempfile::tempdir()?;
+        for (i, opts) in [DbOptions::safe(), DbOptions::fast()].iter().enumerate() {
+            let tmpdb = tdir.path().join(format!("test{}.sqlite", i));
+            let db = opts.clone().pool_size(2).connect(&format!("{}", tmpdb.display()))?;
+            assert_eq!(db.pool_size(), 2);
+        }
+
+        Ok(())
+    }
+}
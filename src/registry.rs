@@ -0,0 +1,335 @@
+// [[file:../database.note::*registry][registry:1]]
+use std::collections::{BTreeMap, HashMap};
+use std::marker::PhantomData;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::codec::{Codec, CodecId};
+use crate::compression::Compression;
+use crate::error::BoxedError;
+
+// Convert data encoded for one version into data for the next version.
+type UpgradeFn = Arc<dyn Fn(CodecId, &[u8]) -> Result<Vec<u8>, BoxedError> + Send + Sync>;
+
+/// Storage settings of a type.
+#[derive(Clone, Default, Derivative)]
+#[derivative(Debug)]
+pub(crate) struct TypeSpec {
+    pub codec: CodecId,
+    // None for using the default of database connection
+    pub compression: Option<Compression>,
+    pub version: u32,
+    // stable name replacing the one derived from type name
+    pub name: Option<String>,
+    // names used before
+    pub aliases: Vec<String>,
+    // store checkpoints as diffs with a full keyframe every n commits
+    pub keyframe_every: Option<u32>,
+    // upgrade functions keyed by the version they upgrade from
+    #[derivative(Debug = "ignore")]
+    upgrades: BTreeMap<u32, UpgradeFn>,
+}
+
+impl TypeSpec {
+    /// Upgrade `data` encoded with `codec` for `version` into data for the
+    /// current version.
+    pub fn upgrade(&self, codec: CodecId, version: u32, data: Vec<u8>) -> Result<Vec<u8>, BoxedError> {
+        if version > self.version {
+            return Err(format!(
+                "data version {} is newer than current version {}",
+                version, self.version
+            )
+            .into());
+        }
+        let mut data = data;
+        for v in version..self.version {
+            let upgrade = self
+                .upgrades
+                .get(&v)
+                .ok_or_else(|| format!("no upgrade registered from version {}", v))?;
+            data = upgrade(codec, &data)?;
+        }
+        Ok(data)
+    }
+}
+
+// keyed by `std::any::type_name`, which does not require `T: 'static` as
+// `TypeId` does.
+fn registry() -> &'static RwLock<HashMap<&'static str, TypeSpec>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<&'static str, TypeSpec>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Return the storage settings of `T`, or the defaults if `T` is not
+/// registered.
+pub(crate) fn spec_of<T: ?Sized>() -> TypeSpec {
+    let registry = registry().read().expect("type registry poisoned");
+    registry.get(std::any::type_name::<T>()).cloned().unwrap_or_default()
+}
+
+/// Return the name of `T` for storing its data.
+pub(crate) fn name_of<T: ?Sized>() -> String {
+    spec_of::<T>().name.unwrap_or_else(|| default_name::<T>())
+}
+
+/// Return the current name and all aliases of `T`.
+pub(crate) fn names_of<T: ?Sized>() -> Vec<String> {
+    let spec = spec_of::<T>();
+    let name = spec.name.unwrap_or_else(|| default_name::<T>());
+    std::iter::once(name).chain(spec.aliases).collect()
+}
+
+fn default_name<T: ?Sized>() -> String {
+    format!("{}.ckpt", std::any::type_name::<T>())
+}
+
+fn update<T: ?Sized>(f: impl FnOnce(&mut TypeSpec)) {
+    let mut registry = registry().write().expect("type registry poisoned");
+    f(registry.entry(std::any::type_name::<T>()).or_default());
+}
+
+/// Handle for changing how values of `T` are stored in `Checkpoint` and
+/// `Collection`. Settings take effect for the whole process, so register
+/// types early, before any read or write.
+///
+/// # Example
+///
+/// ```
+/// use gosh_database::codec::Json;
+///
+/// #[derive(Clone, serde::Serialize, serde::Deserialize)]
+/// struct State {
+///     energy: f64,
+/// }
+///
+/// gosh_database::register::<State>().codec::<Json>();
+/// ```
+pub struct Register<T: ?Sized>(PhantomData<fn() -> Box<T>>);
+
+/// Register storage settings for type `T`.
+pub fn register<T: ?Sized>() -> Register<T> {
+    update::<T>(|_| {});
+    Register(PhantomData)
+}
+
+impl<T: ?Sized> Register<T> {
+    /// Encode new data of `T` with codec `C`. Existing data will still be
+    /// decoded with the codec used for writing.
+    pub fn codec<C: Codec>(self) -> Self {
+        update::<T>(|spec| spec.codec = C::ID);
+        self
+    }
+
+    /// Compress new data of `T` with `compression`, instead of the default
+    /// set for database connection.
+    pub fn compression(self, compression: Compression) -> Self {
+        update::<T>(|spec| spec.compression = Some(compression));
+        self
+    }
+
+    /// Store data of `T` under a stable `name`, instead of the default one
+    /// derived from type name, which changes when the type is moved or
+    /// renamed. Register the default name as an alias to keep existing data
+    /// accessible.
+    pub fn name(self, name: &str) -> Self {
+        update::<T>(|spec| spec.name = Some(name.into()));
+        self
+    }
+
+    /// Add an old name of `T`, under which data can still be found. A name
+    /// derived from type name is in form of `"{type_name}.ckpt"`, e.g.
+    /// `"my_crate::module::State.ckpt"`. Use
+    /// [`DbConnection::rename_aliases`](crate::DbConnection::rename_aliases)
+    /// for rewriting data in place.
+    pub fn alias(self, name: &str) -> Self {
+        update::<T>(|spec| {
+            if !spec.aliases.iter().any(|x| x == name) {
+                spec.aliases.push(name.into());
+            }
+        });
+        self
+    }
+
+    /// Store checkpoints of `T` as binary diffs against the previous
+    /// checkpoint with the same key, with full data every `keyframe_every`
+    /// commits. This saves space for large data changing a little between
+    /// commits, at the cost of rebuilding data from the last full one on
+    /// restore. A diff larger than full data is never stored.
+    pub fn delta(self, keyframe_every: u32) -> Self {
+        update::<T>(|spec| spec.keyframe_every = Some(keyframe_every.max(1)));
+        self
+    }
+
+    /// Set current schema version of `T`, which is stored along with new
+    /// data. Data stored without version are of version 0.
+    pub fn version(self, version: u32) -> Self {
+        update::<T>(|spec| spec.version = version);
+        self
+    }
+
+    /// Register function `f` for upgrading data stored with version `from`
+    /// into version `from + 1`. `Old` is the type of data at version `from`,
+    /// and `New` at version `from + 1`. When data of an older version are
+    /// loaded, upgrades are applied one by one until the current version.
+    pub fn upgrade<Old, New, F>(self, from: u32, f: F) -> Self
+    where
+        Old: DeserializeOwned + 'static,
+        New: Serialize + 'static,
+        F: Fn(Old) -> New + Send + Sync + 'static,
+    {
+        let upgrade: UpgradeFn = Arc::new(move |codec, data| {
+            let old: Old = codec.decode(data)?;
+            codec.encode(&f(old))
+        });
+        update::<T>(|spec| {
+            spec.upgrades.insert(from, upgrade);
+        });
+        self
+    }
+}
+
+impl<T: Named> Register<T> {
+    /// Set name and aliases as declared in [`Named`].
+    pub fn named(self) -> Self {
+        T::ALIASES
+            .iter()
+            .fold(self.name(T::NAME), |reg, alias| reg.alias(alias))
+    }
+}
+
+/// Declare a stable name of a type for stored data.
+///
+/// # Example
+///
+/// ```
+/// use gosh_database::Named;
+///
+/// #[derive(Clone, serde::Serialize, serde::Deserialize)]
+/// struct State {
+///     energy: f64,
+/// }
+///
+/// impl Named for State {
+///     const NAME: &'static str = "optimizer-state";
+///     const ALIASES: &'static [&'static str] = &["old_crate::State.ckpt"];
+/// }
+///
+/// gosh_database::register::<State>().named();
+/// ```
+pub trait Named {
+    /// Stable name.
+    const NAME: &'static str;
+
+    /// Names used before.
+    const ALIASES: &'static [&'static str] = &[];
+}
+
+impl<T: Versioned> Register<T> {
+    /// Set version and upgrades as declared in [`Versioned`].
+    pub fn versioned(self) -> Self {
+        T::upgrades(self.version(T::VERSION))
+    }
+}
+
+/// Declare schema version of a type for stored data.
+///
+/// # Example
+///
+/// ```
+/// use gosh_database::{Register, Versioned};
+///
+/// #[derive(serde::Deserialize)]
+/// struct StateV0 {
+///     energy: f64,
+/// }
+///
+/// #[derive(Clone, serde::Serialize, serde::Deserialize)]
+/// struct State {
+///     energy: f64,
+///     step: usize,
+/// }
+///
+/// impl Versioned for State {
+///     const VERSION: u32 = 1;
+///
+///     fn upgrades(reg: Register<Self>) -> Register<Self> {
+///         reg.upgrade(0, |old: StateV0| State { energy: old.energy, step: 0 })
+///     }
+/// }
+///
+/// gosh_database::register::<State>().versioned();
+/// ```
+pub trait Versioned {
+    /// Current schema version.
+    const VERSION: u32;
+
+    /// Register upgrades from older versions. See [`Register::upgrade`].
+    fn upgrades(reg: Register<Self>) -> Register<Self> {
+        reg
+    }
+}
+// registry:1 ends here
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blob::Blob;
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize)]
+    struct StateV0 {
+        energy: f64,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct StateV1 {
+        energy: f64,
+        step: usize,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct State {
+        energy: f64,
+        step: usize,
+        label: String,
+    }
+
+    impl Versioned for State {
+        const VERSION: u32 = 2;
+
+        fn upgrades(reg: Register<Self>) -> Register<Self> {
+            reg.upgrade(0, |old: StateV0| StateV1 {
+                energy: old.energy,
+                step: 0,
+            })
+            .upgrade(1, |old: StateV1| State {
+                energy: old.energy,
+                step: old.step,
+                label: "upgraded".into(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_upgrade() {
+        register::<State>().versioned();
+        assert_eq!(spec_of::<State>().version, 2);
+
+        let blob = Blob {
+            data: bincode::serialize(&StateV0 { energy: -1.0 }).unwrap(),
+            codec: 0,
+            compression: 0,
+            version: 0,
+        };
+        let x: State = blob.decode("test").unwrap();
+        assert_eq!(x.energy, -1.0);
+        assert_eq!(x.step, 0);
+        assert_eq!(x.label, "upgraded");
+
+        // newer than current version
+        let blob = Blob { version: 3, ..blob };
+        assert!(blob.decode::<State>("test").is_err());
+    }
+}
@@ -0,0 +1,237 @@
+// [[file:../database.note::*retention][retention:1]]
+use crate::checkpoint::CheckpointInfo;
+use crate::*;
+
+/// Which checkpoints of the same key to keep when pruning. The latest
+/// checkpoint of each branch, and checkpoints with a label or tags are always
+/// kept. Members of a [`CheckpointGroup`](crate::CheckpointGroup) are only
+/// pruned together with their group, see
+/// [`CheckpointGroup::prune`](crate::CheckpointGroup::prune).
+///
+/// Policies are based on commit sequence numbers, so pruning repeatedly with
+/// the same policy gives the same result as pruning once, unless checkpoints
+/// are [imported](crate::CheckpointDb::import) in between, which shifts the
+/// sequence numbers of those created later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RetentionPolicy {
+    /// Keep all checkpoints.
+    #[default]
+    KeepAll,
+    /// Keep the last `n` checkpoints.
+    KeepLast(usize),
+    /// Keep every `k`-th checkpoint in commit order.
+    KeepEvery(usize),
+    /// Keep checkpoints more sparsely the older they are: about one in each
+    /// span of 1, 2, 4, 8, ... commits back from the latest. The first
+    /// checkpoint is always kept.
+    Exponential,
+    /// Keep the latest checkpoints with total size of stored data within the
+    /// given bytes.
+    MaxBytes(u64),
+}
+
+/// A checkpoint or checkpoint group to be selected by [`RetentionPolicy`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RetentionItem {
+    /// Commit sequence number.
+    pub seq: i64,
+    /// Size of stored data in bytes.
+    pub size: u64,
+    /// Always kept if true.
+    pub pinned: bool,
+}
+
+impl RetentionPolicy {
+    /// Return the ids of checkpoints in `infos` to be deleted. `infos` are
+    /// the checkpoints of the same key ordered by slot.
+    pub(crate) fn select_pruned(&self, infos: &[CheckpointInfo]) -> Vec<i32> {
+        let mut items: Vec<_> = infos
+            .iter()
+            .map(|x| RetentionItem {
+                seq: x.seq,
+                size: x.size as u64,
+                pinned: x.meta.label.is_some() || !x.meta.tags.is_empty(),
+            })
+            .collect();
+        // tips of branches
+        let mut tips = std::collections::HashMap::new();
+        for (i, x) in infos.iter().enumerate() {
+            tips.insert(x.branch, i);
+        }
+        for i in tips.into_values() {
+            items[i].pinned = true;
+        }
+
+        self.select_dropped(&items)
+            .into_iter()
+            .map(|i| &infos[i])
+            // groups are pruned as a whole, see `CheckpointGroup::prune`
+            .filter(|x| x.group_id.is_none())
+            .map(|x| x.id)
+            .collect()
+    }
+
+    /// Return the indices of `items` to be deleted. `items` are ordered by
+    /// sequence number.
+    pub(crate) fn select_dropped(&self, items: &[RetentionItem]) -> Vec<usize> {
+        let latest = match items.last() {
+            Some(x) => x.seq,
+            None => return vec![],
+        };
+        let keep: Vec<bool> = match *self {
+            RetentionPolicy::KeepAll => return vec![],
+            RetentionPolicy::KeepLast(n) => {
+                let m = items.len().saturating_sub(n);
+                (0..items.len()).map(|i| i >= m).collect()
+            }
+            RetentionPolicy::KeepEvery(k) => items.iter().map(|x| x.seq % k.max(1) as i64 == 0).collect(),
+            RetentionPolicy::Exponential => {
+                // bucket b holds checkpoints of age in [2^b - 1, 2^(b+1) - 2];
+                // keep the oldest in each bucket.
+                let bucket = |x: &RetentionItem| 64 - ((latest - x.seq).max(0) as u64 + 1).leading_zeros();
+                let mut keep = vec![false; items.len()];
+                let mut last_bucket = None;
+                for (i, x) in items.iter().enumerate() {
+                    let b = bucket(x);
+                    if last_bucket != Some(b) {
+                        keep[i] = true;
+                        last_bucket = Some(b);
+                    }
+                }
+                keep
+            }
+            RetentionPolicy::MaxBytes(max) => {
+                let mut total = 0;
+                let mut keep = vec![false; items.len()];
+                for (i, x) in items.iter().enumerate().rev() {
+                    total += x.size;
+                    if total > max {
+                        break;
+                    }
+                    keep[i] = true;
+                }
+                keep
+            }
+        };
+
+        items
+            .iter()
+            .zip(keep)
+            .enumerate()
+            .filter(|(_, (x, keep))| !keep && !x.pinned)
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+impl FromStr for RetentionPolicy {
+    type Err = Error;
+
+    /// Parse from "all", "last:<n>", "every:<k>", "exp", or "bytes:<size>".
+    /// Size can have a suffix of K, M or G, e.g. "bytes:500M".
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.to_lowercase();
+        let v = match s.split_once(':') {
+            Some(("last", n)) => RetentionPolicy::KeepLast(n.parse()?),
+            Some(("every", k)) if k != "0" => RetentionPolicy::KeepEvery(k.parse()?),
+            Some(("bytes", size)) => {
+                let (n, unit) = match size.char_indices().last() {
+                    Some((i, 'k')) => (&size[..i], 1 << 10),
+                    Some((i, 'm')) => (&size[..i], 1 << 20),
+                    Some((i, 'g')) => (&size[..i], 1 << 30),
+                    _ => (size, 1),
+                };
+                RetentionPolicy::MaxBytes(n.parse::<u64>()? * unit)
+            }
+            None if s == "all" => RetentionPolicy::KeepAll,
+            None if s == "exp" => RetentionPolicy::Exponential,
+            _ => bail!("invalid retention policy: {}", s),
+        };
+        Ok(v)
+    }
+}
+// retention:1 ends here
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::checkpoint::CheckpointMeta;
+
+    fn infos(n: usize) -> Vec<CheckpointInfo> {
+        let t = chrono::Utc::now().naive_utc();
+        (0..n)
+            .map(|i| CheckpointInfo {
+                slot: i,
+                id: i as i32 + 1,
+                seq: i as i64 + 1,
+                key: "test".into(),
+                run: String::new(),
+                ctime: t,
+                mtime: t,
+                size: 10,
+                codec: None,
+                compression: None,
+                version: 0,
+                meta: CheckpointMeta::default(),
+                group_id: None,
+                base_id: None,
+                parent_id: None,
+                branch: 0,
+            })
+            .collect()
+    }
+
+    fn kept(policy: RetentionPolicy, infos: &[CheckpointInfo]) -> Vec<i64> {
+        let pruned = policy.select_pruned(infos);
+        infos
+            .iter()
+            .filter(|x| !pruned.contains(&x.id))
+            .map(|x| x.seq)
+            .collect()
+    }
+
+    #[test]
+    fn test_retention() -> Result<()> {
+        let all = infos(10);
+        assert_eq!(kept(RetentionPolicy::KeepAll, &all).len(), 10);
+        assert_eq!(kept(RetentionPolicy::KeepLast(3), &all), [8, 9, 10]);
+        assert_eq!(kept(RetentionPolicy::KeepEvery(4), &all), [4, 8, 10]);
+        assert_eq!(kept(RetentionPolicy::MaxBytes(35), &all), [8, 9, 10]);
+        assert_eq!(kept(RetentionPolicy::MaxBytes(0), &all), [10]);
+        assert_eq!(kept(RetentionPolicy::Exponential, &all), [1, 4, 8, 10]);
+        assert!(kept(RetentionPolicy::KeepAll, &[]).is_empty());
+
+        // tagged checkpoints are kept
+        let mut all = all;
+        all[0].meta = CheckpointMeta::new().tag("initial");
+        assert_eq!(kept(RetentionPolicy::KeepLast(1), &all), [1, 10]);
+
+        // so are the latest in each branch
+        all[0].meta = CheckpointMeta::default();
+        all[4].branch = 1;
+        assert_eq!(kept(RetentionPolicy::KeepLast(1), &all), [5, 10]);
+
+        // pruning with exponential policy keeps a logarithmic number of
+        // checkpoints
+        let mut all = vec![];
+        for x in infos(1000) {
+            all.push(x);
+            let pruned = RetentionPolicy::Exponential.select_pruned(&all);
+            all.retain(|x| !pruned.contains(&x.id));
+        }
+        assert!(all.len() <= 11);
+        assert_eq!(all[0].seq, 1);
+        assert_eq!(all.last().unwrap().seq, 1000);
+
+        assert_eq!("last:5".parse::<RetentionPolicy>()?, RetentionPolicy::KeepLast(5));
+        assert_eq!(
+            "bytes:2M".parse::<RetentionPolicy>()?,
+            RetentionPolicy::MaxBytes(2 << 20)
+        );
+        assert_eq!("EXP".parse::<RetentionPolicy>()?, RetentionPolicy::Exponential);
+        assert!("every:0".parse::<RetentionPolicy>().is_err());
+        assert!("first:5".parse::<RetentionPolicy>().is_err());
+
+        Ok(())
+    }
+}
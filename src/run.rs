@@ -0,0 +1,126 @@
+// [[file:../database.note::*run][run:1]]
+use std::collections::{BTreeSet, HashMap};
+
+use diesel::sql_types::{Integer, Nullable, Text};
+
+use crate::checkpoint::delete_checkpoints;
+use crate::*;
+
+// id, group_id, base_id and parent_id of a checkpoint
+type LinkRow = (i32, Option<i32>, Option<i32>, Option<i32>);
+
+impl DbConnection {
+    /// Return names of runs having checkpoints or checkpoint groups, in
+    /// alphabetical order. See [`with_run`](Self::with_run).
+    pub fn runs(&self) -> Result<Vec<String>, DbError> {
+        let conn = self.reader()?;
+        let mut runs: BTreeSet<String> = BTreeSet::new();
+        {
+            use crate::schema::checkpoints::dsl::*;
+            runs.extend(checkpoints.select(run).distinct().load::<String>(&*conn)?);
+        }
+        {
+            use crate::schema::checkpoint_groups::dsl::*;
+            runs.extend(checkpoint_groups.select(run).distinct().load::<String>(&*conn)?);
+        }
+        Ok(runs.into_iter().collect())
+    }
+
+    /// Copy all checkpoints and checkpoint groups in run `from` into run
+    /// `to`, keeping their slots, metadata and history. Return the number of
+    /// copied checkpoints. Fails with [`DbError::RunExists`] if `to` is not
+    /// empty.
+    pub fn copy_run(&self, from: &str, to: &str) -> Result<usize, DbError> {
+        self.transaction(|tx| {
+            let conn = tx.writer();
+            if tx.runs()?.iter().any(|x| x == to) {
+                return Err(DbError::RunExists { run: to.into() });
+            }
+
+            // map old ids to new ones
+            let mut groups: HashMap<i32, i32> = HashMap::new();
+            {
+                use crate::schema::checkpoint_groups::dsl::*;
+                let old_ids: Vec<i32> = checkpoint_groups
+                    .filter(run.eq(from))
+                    .select(id)
+                    .order(id.asc())
+                    .load(&*conn)?;
+                for old_id in old_ids {
+                    diesel::sql_query(
+                        "INSERT INTO checkpoint_groups (name, seq, ctime, run)
+                         SELECT name, seq, ctime, ? FROM checkpoint_groups WHERE id = ?",
+                    )
+                    .bind::<Text, _>(to)
+                    .bind::<Integer, _>(old_id)
+                    .execute(&*conn)?;
+                    let new_id: i32 = checkpoint_groups.select(id).order(id.desc()).first(&*conn)?;
+                    groups.insert(old_id, new_id);
+                }
+            }
+
+            use crate::schema::checkpoints::dsl::*;
+            // bases and parents are committed before, so they are mapped in
+            // time when copying in order of id.
+            let rows: Vec<LinkRow> = checkpoints
+                .filter(run.eq(from))
+                .select((id, group_id, base_id, parent_id))
+                .order(id.asc())
+                .load(&*conn)?;
+            let mut ckpts: HashMap<i32, i32> = HashMap::new();
+            for (old_id, old_group, old_base, old_parent) in rows {
+                let map = |ids: &HashMap<i32, i32>, x: Option<i32>| x.and_then(|x| ids.get(&x).copied());
+                diesel::sql_query(
+                    "INSERT INTO checkpoints (key, run, seq, data, ctime, mtime, codec, compression, version,
+                       label, step, note, group_id, base_id, parent_id, branch)
+                     SELECT key, ?, seq, data, ctime, mtime, codec, compression, version,
+                       label, step, note, ?, ?, ?, branch FROM checkpoints WHERE id = ?",
+                )
+                .bind::<Text, _>(to)
+                .bind::<Nullable<Integer>, _>(map(&groups, old_group))
+                .bind::<Nullable<Integer>, _>(map(&ckpts, old_base))
+                .bind::<Nullable<Integer>, _>(map(&ckpts, old_parent))
+                .bind::<Integer, _>(old_id)
+                .execute(&*conn)?;
+                let new_id: i32 = checkpoints.select(id).order(id.desc()).first(&*conn)?;
+                diesel::sql_query(
+                    "INSERT INTO checkpoint_tags (checkpoint_id, tag)
+                     SELECT ?, tag FROM checkpoint_tags WHERE checkpoint_id = ?",
+                )
+                .bind::<Integer, _>(new_id)
+                .bind::<Integer, _>(old_id)
+                .execute(&*conn)?;
+                diesel::sql_query(
+                    "INSERT INTO checkpoint_values (checkpoint_id, name, value)
+                     SELECT ?, name, value FROM checkpoint_values WHERE checkpoint_id = ?",
+                )
+                .bind::<Integer, _>(new_id)
+                .bind::<Integer, _>(old_id)
+                .execute(&*conn)?;
+                ckpts.insert(old_id, new_id);
+            }
+            Ok(ckpts.len())
+        })
+    }
+
+    /// Delete all checkpoints and checkpoint groups in `run`. Return the
+    /// number of deleted checkpoints. Use [`vacuum`](Self::vacuum) to reclaim
+    /// the space afterwards.
+    pub fn delete_run(&self, run: &str) -> Result<usize, DbError> {
+        let ckpt_run = run;
+        self.transaction(|tx| {
+            let conn = tx.writer();
+            let ids: Vec<i32> = {
+                use crate::schema::checkpoints::dsl::*;
+                checkpoints.filter(run.eq(ckpt_run)).select(id).load(&*conn)?
+            };
+            let n = delete_checkpoints(tx, &ids)?;
+            {
+                use crate::schema::checkpoint_groups::dsl::*;
+                diesel::delete(checkpoint_groups.filter(run.eq(ckpt_run))).execute(&*conn)?;
+            }
+            Ok(n)
+        })
+    }
+}
+// run:1 ends here
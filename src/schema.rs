@@ -2,9 +2,46 @@ table! {
     checkpoints (id) {
         id -> Integer,
         key -> Text,
+        seq -> BigInt,
         data -> Binary,
         ctime -> Timestamp,
         mtime -> Timestamp,
+        codec -> Integer,
+        compression -> Integer,
+        version -> Integer,
+        label -> Nullable<Text>,
+        step -> Nullable<BigInt>,
+        note -> Nullable<Text>,
+        group_id -> Nullable<Integer>,
+        base_id -> Nullable<Integer>,
+        parent_id -> Nullable<Integer>,
+        branch -> Integer,
+        run -> Text,
+    }
+}
+
+table! {
+    checkpoint_groups (id) {
+        id -> Integer,
+        name -> Text,
+        seq -> BigInt,
+        ctime -> Timestamp,
+        run -> Text,
+    }
+}
+
+table! {
+    checkpoint_tags (checkpoint_id, tag) {
+        checkpoint_id -> Integer,
+        tag -> Text,
+    }
+}
+
+table! {
+    checkpoint_values (checkpoint_id, name) {
+        checkpoint_id -> Integer,
+        name -> Text,
+        value -> Nullable<Double>,
     }
 }
 
@@ -16,6 +53,9 @@ table! {
         data -> Binary,
         ctime -> Timestamp,
         mtime -> Timestamp,
+        codec -> Integer,
+        compression -> Integer,
+        version -> Integer,
     }
 }
 
@@ -50,6 +90,9 @@ table! {
 
 allow_tables_to_appear_in_same_query!(
     checkpoints,
+    checkpoint_groups,
+    checkpoint_tags,
+    checkpoint_values,
     kvstore,
     models,
     molecules,
@@ -0,0 +1,130 @@
+// [[file:../database.note::*signal][signal:1]]
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::{Handle, Signals};
+
+use crate::checkpoint::{Checkpoint, CheckpointDb};
+use crate::*;
+
+/// Handle of the signal handler registered by
+/// [`CheckpointDb::commit_on_signal`]. When dropped, the handler is removed,
+/// and SIGINT and SIGTERM terminate the process as by default.
+pub struct SignalGuard {
+    handle: Handle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CheckpointDb {
+    /// Commit the state returned by `provider` as a final checkpoint when
+    /// SIGINT or SIGTERM arrives, then exit the process with status 128 plus
+    /// the signal number. Nothing is committed if `provider` returns None.
+    ///
+    /// `provider` is called on a background thread, so share the state
+    /// through a lock and keep it updated, e.g. once per step.
+    ///
+    /// The process exits without running destructors, so commits pending in
+    /// a [`CheckpointWriter`](crate::CheckpointWriter) are lost unless
+    /// `provider` calls [`flush`](crate::CheckpointWriter::flush) on it.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use gosh_database::CheckpointDb;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let chk = CheckpointDb::new("/tmp/test.sqlite");
+    /// let state = Arc::new(Mutex::new(vec![0.0; 3]));
+    /// let latest = state.clone();
+    /// let _guard = chk
+    ///     .commit_on_signal(move || Some(latest.lock().unwrap().clone()))
+    ///     .unwrap();
+    /// for i in 0..100 {
+    ///     state.lock().unwrap()[0] = i as f64;
+    /// }
+    /// ```
+    pub fn commit_on_signal<T, F>(&self, provider: F) -> Result<SignalGuard>
+    where
+        T: Checkpoint,
+        F: Fn() -> Option<T> + Send + 'static,
+    {
+        let mut signals = Signals::new([SIGINT, SIGTERM]).context("failed to register signal handler")?;
+        let handle = signals.handle();
+        let chk = self.clone();
+        let thread = std::thread::Builder::new()
+            .name("checkpoint-signal".into())
+            .spawn(move || {
+                // ends when the handle is closed
+                if let Some(sig) = signals.forever().next() {
+                    warn!("received signal {}, committing final checkpoint", sig);
+                    commit_final(&chk, &provider);
+                    std::process::exit(128 + sig);
+                }
+            })
+            .context("failed to spawn signal handling thread")?;
+
+        Ok(SignalGuard {
+            handle,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Commit the state from `provider`. Errors are logged, since the process is
+/// about to exit.
+fn commit_final<T: Checkpoint>(chk: &CheckpointDb, provider: &dyn Fn() -> Option<T>) -> bool {
+    let state = match provider() {
+        Some(state) => state,
+        None => return false,
+    };
+    match chk.commit(&state) {
+        Ok(committed) => committed,
+        Err(e) => {
+            error!("failed to commit final checkpoint: {:?}", e);
+            false
+        }
+    }
+}
+
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        self.handle.close();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        // signal-hook leaves the signals ignored after unregistering, so
+        // terminate on them again.
+        let always = Arc::new(AtomicBool::new(true));
+        for sig in [SIGINT, SIGTERM] {
+            if let Err(e) = signal_hook::flag::register_conditional_default(sig, always.clone()) {
+                warn!("failed to restore default handler of signal {}: {}", sig, e);
+            }
+        }
+    }
+}
+// signal:1 ends here
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_commit_on_signal() -> Result<()> {
+        let tdir = tempfile::tempdir()?;
+        let tmpdb = tdir.path().join("test.sqlite");
+        let chk = CheckpointDb::new(&tmpdb);
+
+        assert!(commit_final(&chk, &|| Some(1.0)));
+        assert!(!commit_final::<f64>(&chk, &|| None));
+        assert_eq!(chk.load_from_latest::<f64>()?, 1.0);
+
+        // registered handler is removed on drop, without committing
+        let guard = chk.commit_on_signal(|| Some(2.0))?;
+        drop(guard);
+        assert_eq!(chk.list::<f64>()?.len(), 1);
+
+        Ok(())
+    }
+}
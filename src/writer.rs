@@ -0,0 +1,163 @@
+// [[file:../database.note::*writer][writer:1]]
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::checkpoint::{Checkpoint, CheckpointMeta};
+use crate::retention::RetentionPolicy;
+use crate::*;
+
+type CommitFn = Box<dyn FnOnce(&DbConnection) -> Result<(), DbError> + Send>;
+
+enum Job {
+    Commit(CommitFn),
+    // reply when all jobs before are done
+    Flush(SyncSender<()>),
+}
+
+/// Commit checkpoints on a background thread, so that slow disks do not
+/// stall the calculation.
+///
+/// Committed values are cloned immediately, then encoded and written in
+/// order on the background thread. When `capacity` commits are pending,
+/// further commits block until one is written. A failed write is reported
+/// by the next call of `commit` or `flush`. All pending commits are written
+/// when the writer is dropped, but not if the process exits before, e.g. by
+/// `std::process::exit`.
+///
+/// # Example
+///
+/// ```no_run
+/// use gosh_database::{CheckpointWriter, DbConnection};
+///
+/// let db = DbConnection::connect("/tmp/test.sqlite").unwrap();
+/// let writer = CheckpointWriter::new(&db);
+/// let mut x = vec![0.0; 1000];
+/// for i in 0..100 {
+///     x[i] = 1.0;
+///     writer.commit(&x).unwrap();
+/// }
+/// writer.flush().unwrap();
+/// ```
+pub struct CheckpointWriter {
+    sender: Option<SyncSender<Job>>,
+    handle: Option<JoinHandle<()>>,
+    // the first error from background thread not reported yet
+    error: Arc<Mutex<Option<DbError>>>,
+    retention: Option<RetentionPolicy>,
+}
+
+impl CheckpointWriter {
+    /// Writer for committing into `db`, with at most 4 pending commits.
+    pub fn new(db: &DbConnection) -> Self {
+        Self::with_capacity(db, 4)
+    }
+
+    /// Writer for committing into `db`, with at most `capacity` pending
+    /// commits.
+    pub fn with_capacity(db: &DbConnection, capacity: usize) -> Self {
+        let (sender, receiver) = sync_channel(capacity);
+        let error = Arc::new(Mutex::new(None));
+        let db = db.clone();
+        let errors = error.clone();
+        let handle = std::thread::Builder::new()
+            .name("checkpoint-writer".into())
+            .spawn(move || run(db, receiver, errors))
+            .expect("failed to spawn checkpoint writer thread");
+
+        Self {
+            sender: Some(sender),
+            handle: Some(handle),
+            error,
+            retention: None,
+        }
+    }
+
+    /// Prune checkpoints according to `policy` after each commit.
+    pub fn retention(mut self, policy: RetentionPolicy) -> Self {
+        self.retention = Some(policy);
+        self
+    }
+
+    /// Commit a snapshot of `value` in background.
+    pub fn commit<T: Checkpoint + Send + 'static>(&self, value: &T) -> Result<(), DbError> {
+        self.commit_with(value, &CheckpointMeta::default())
+    }
+
+    /// Commit a snapshot of `value` with metadata `meta` in background.
+    pub fn commit_with<T: Checkpoint + Send + 'static>(&self, value: &T, meta: &CheckpointMeta) -> Result<(), DbError> {
+        self.take_error()?;
+
+        let value = value.clone();
+        let meta = meta.clone();
+        let retention = self.retention;
+        let job: CommitFn = Box::new(move |db| {
+            value.commit_checkpoint_with(db, &meta)?;
+            if let Some(policy) = retention {
+                T::prune_checkpoints(db, policy)?;
+            }
+            Ok(())
+        });
+        self.send(Job::Commit(job))
+    }
+
+    /// Wait until all pending commits are written. Return the error of any
+    /// failed write.
+    pub fn flush(&self) -> Result<(), DbError> {
+        let (sender, receiver) = sync_channel(1);
+        self.send(Job::Flush(sender))?;
+        // the writer thread stops only after replying
+        let _ = receiver.recv();
+        self.take_error()
+    }
+
+    fn send(&self, job: Job) -> Result<(), DbError> {
+        let sender = self.sender.as_ref().expect("checkpoint writer sender");
+        sender.send(job).map_err(|_| {
+            let e = std::io::Error::new(std::io::ErrorKind::BrokenPipe, "checkpoint writer stopped");
+            DbError::Io(e)
+        })
+    }
+
+    fn take_error(&self) -> Result<(), DbError> {
+        match self.error.lock().expect("checkpoint writer poisoned").take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for CheckpointWriter {
+    fn drop(&mut self) {
+        // the background thread stops after all pending jobs are done
+        drop(self.sender.take());
+        if let Some(handle) = self.handle.take() {
+            if handle.join().is_err() {
+                error!("checkpoint writer thread panicked");
+            }
+        }
+        if let Err(e) = self.take_error() {
+            error!("failed to write checkpoint: {}", e);
+        }
+    }
+}
+
+fn run(db: DbConnection, receiver: Receiver<Job>, error: Arc<Mutex<Option<DbError>>>) {
+    for job in receiver {
+        match job {
+            Job::Commit(f) => {
+                if let Err(e) = f(&db) {
+                    warn!("failed to write checkpoint: {}", e);
+                    let mut error = error.lock().expect("checkpoint writer poisoned");
+                    if error.is_none() {
+                        *error = Some(e);
+                    }
+                }
+            }
+            Job::Flush(reply) => {
+                let _ = reply.send(());
+            }
+        }
+    }
+}
+// writer:1 ends here
@@ -1,8 +1,11 @@
 // [[file:../database.note::*tests][tests:1]]
 use gosh_core::*;
 use gosh_database::prelude::*;
-use gosh_database::DbConnection;
+use gosh_database::{
+    CheckpointDb, CheckpointGroup, CheckpointMeta, CheckpointWriter, DbConnection, DbError, RetentionPolicy,
+};
 
+use gut::cli::*;
 use gut::prelude::*;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -31,4 +34,742 @@ fn test_checkpoint_and_collection() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_parallel_reads() -> Result<()> {
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let db = DbConnection::connect_with_pool_size(&format!("{}", tmpdb.display()), 4)?;
+    assert_eq!(db.pool_size(), 4);
+
+    for i in 0..10 {
+        let x = Test { data: i as f64 };
+        x.put_into_collection(&db, &format!("test{}", i))?;
+    }
+
+    let values: Vec<f64> = (0..100)
+        .into_par_iter()
+        .map(|i| Test::get_from_collection(&db, &format!("test{}", i % 10)).map(|x| x.data))
+        .collect::<Result<_, DbError>>()?;
+    for (i, v) in values.into_iter().enumerate() {
+        assert_eq!(v, (i % 10) as f64);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_transaction() -> Result<()> {
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let db = DbConnection::connect(&format!("{}", tmpdb.display()))?;
+
+    // all or nothing
+    let x = Test { data: 1.0 };
+    let r: Result<()> = db.transaction(|tx| {
+        x.commit_checkpoint(tx)?;
+        x.put_into_collection(tx, "x")?;
+        // uncommitted writes are visible through the handle
+        assert_eq!(Test::collection_size(tx)?, 1);
+        bail!("abort");
+    });
+    assert!(r.is_err());
+    assert_eq!(Test::collection_size(&db)?, 0);
+    assert!(Test::from_checkpoint_n(&db, -1).is_err());
+
+    db.transaction(|tx| {
+        x.commit_checkpoint(tx)?;
+        x.put_into_collection(tx, "x")?;
+        // nested transaction is rolled back alone
+        let r: Result<()> = tx.transaction(|tx| {
+            x.put_into_collection(tx, "y")?;
+            bail!("abort");
+        });
+        assert!(r.is_err());
+        Ok_(())
+    })?;
+    assert_eq!(Test::collection_size(&db)?, 1);
+    assert_eq!(Test::from_checkpoint_n(&db, -1)?.data, 1.0);
+
+    Ok(())
+}
+
+#[test]
+fn test_codec() -> Result<()> {
+    use gosh_database::codec::*;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Test {
+        data: f64,
+    }
+
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let db = DbConnection::connect(&format!("{}", tmpdb.display()))?;
+
+    gosh_database::register::<Test>().codec::<Json>();
+    Test { data: 1.0 }.put_into_collection(&db, "json")?;
+    Test { data: 1.0 }.commit_checkpoint(&db)?;
+
+    // data written before are still decoded with JSON
+    gosh_database::register::<Test>().codec::<Cbor>();
+    Test { data: 2.0 }.put_into_collection(&db, "cbor")?;
+    assert_eq!(Test::get_from_collection(&db, "json")?.data, 1.0);
+    assert_eq!(Test::get_from_collection(&db, "cbor")?.data, 2.0);
+    assert_eq!(Test::from_checkpoint_n(&db, -1)?.data, 1.0);
+
+    Ok(())
+}
+
+#[test]
+fn test_compression() -> Result<()> {
+    use diesel::prelude::*;
+    use diesel::sql_types::{Integer, Text};
+    use gosh_database::{Compression, DbOptions};
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Test {
+        data: Vec<f64>,
+    }
+
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let db = DbOptions::new()
+        .compression(Compression::Lz4)
+        .connect(&format!("{}", tmpdb.display()))?;
+
+    let x = Test { data: vec![1.0; 1000] };
+    x.put_into_collection(&db, "lz4")?;
+    gosh_database::register::<Test>().compression(Compression::Zstd(19));
+    x.put_into_collection(&db, "zstd")?;
+    gosh_database::register::<Test>().compression(Compression::None);
+    x.put_into_collection(&db, "none")?;
+
+    for k in ["lz4", "zstd", "none"] {
+        assert_eq!(Test::get_from_collection(&db, k)?.data, x.data);
+    }
+
+    // stored compressed as set for each key
+    let conn = diesel::SqliteConnection::establish(&format!("{}", tmpdb.display()))?;
+    let stored: Vec<(String, i32, i32)> =
+        diesel::dsl::sql::<(Text, Integer, Integer)>("SELECT key, compression, length(data) FROM kvstore ORDER BY key")
+            .load(&conn)?;
+    let (lz4, none, zstd) = (&stored[0], &stored[1], &stored[2]);
+    assert_eq!(
+        [lz4.0.as_str(), none.0.as_str(), zstd.0.as_str()],
+        ["lz4", "none", "zstd"]
+    );
+    // ids of none, zstd and lz4
+    assert_eq!(none.1, 0);
+    assert_eq!(none.2, 8008);
+    assert_eq!(zstd.1, 1);
+    assert_eq!(lz4.1, 2);
+    assert!(lz4.2 < none.2 / 10);
+    assert!(zstd.2 < none.2 / 10);
+    assert_ne!(lz4.2, zstd.2);
+
+    Ok(())
+}
+
+#[test]
+fn test_stable_name() -> Result<()> {
+    mod old {
+        #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+        pub struct State {
+            pub data: f64,
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct State {
+        data: f64,
+    }
+
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let db = DbConnection::connect(&format!("{}", tmpdb.display()))?;
+
+    // data stored under the name derived from old type path
+    let x = old::State { data: 1.0 };
+    x.commit_checkpoint(&db)?;
+    x.put_into_collection(&db, "x")?;
+    let old_name = old::State::checkpoint_name();
+
+    gosh_database::register::<State>().name("state").alias(&old_name);
+    assert_eq!(State::checkpoint_name(), "state");
+    assert_eq!(State::from_checkpoint_n(&db, -1)?.data, 1.0);
+    assert_eq!(State::get_from_collection(&db, "x")?.data, 1.0);
+
+    // current name takes precedence over aliases
+    State { data: 2.0 }.put_into_collection(&db, "x")?;
+    assert_eq!(State::get_from_collection(&db, "x")?.data, 2.0);
+    assert_eq!(State::keys(&db)?, ["x"]);
+    assert_eq!(State::collection_size(&db)?, 1);
+    let items = State::list_collection(&db)?;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].data, 2.0);
+    let items = State::list_after(&db, None, 10)?;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].1.data, 2.0);
+
+    // rewrite in place
+    assert_eq!(db.rename_aliases::<State>()?, 1);
+    assert_eq!(State::collection_size(&db)?, 1);
+    assert_eq!(State::get_from_collection(&db, "x")?.data, 2.0);
+    assert!(old::State::from_checkpoint_n(&db, -1).unwrap_err().is_not_found());
+    assert_eq!(State::from_checkpoint_n(&db, -1)?.data, 1.0);
+
+    Ok(())
+}
+
+#[test]
+fn test_checkpoint_meta() -> Result<()> {
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct State {
+        data: f64,
+    }
+
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let db = DbConnection::connect(&format!("{}", tmpdb.display()))?;
+
+    for i in 0..5 {
+        let x = State { data: i as f64 };
+        let mut meta = CheckpointMeta::new().step(i * 10).value("energy", -(i as f64));
+        if i % 2 == 0 {
+            meta = meta.tag("even");
+        }
+        if i == 3 {
+            meta = meta.label("relaxed").tag("converged").note("done");
+        }
+        x.commit_checkpoint_with(&db, &meta)?;
+    }
+    State { data: 5.0 }.commit_checkpoint(&db)?;
+
+    assert_eq!(State::from_checkpoint_labeled(&db, "relaxed")?.data, 3.0);
+    assert_eq!(State::from_checkpoint_tagged(&db, "converged")?.data, 3.0);
+    assert_eq!(State::from_checkpoint_tagged(&db, "even")?.data, 4.0);
+    assert_eq!(State::from_checkpoint_step(&db, 20)?.data, 2.0);
+    let e = State::from_checkpoint_tagged(&db, "missing").unwrap_err();
+    assert!(matches!(e, DbError::CheckpointNotFound { .. }));
+    assert!(e.is_not_found());
+
+    let ckpts = State::checkpoints(&db)?;
+    assert_eq!(ckpts.len(), 6);
+    let meta = &ckpts[3].meta;
+    assert_eq!(meta.label.as_deref(), Some("relaxed"));
+    assert_eq!(meta.step, Some(30));
+    assert_eq!(meta.tags, ["converged"]);
+    assert_eq!(meta.note.as_deref(), Some("done"));
+    assert_eq!(meta.values["energy"], -3.0);
+    assert_eq!(ckpts[5].meta, CheckpointMeta::default());
+
+    // diverging values
+    let meta = CheckpointMeta::new()
+        .value("energy", f64::NAN)
+        .value("force", f64::INFINITY);
+    State { data: 6.0 }.commit_checkpoint_with(&db, &meta)?;
+    let values = &State::checkpoints(&db)?[6].meta.values;
+    assert!(values["energy"].is_nan());
+    assert_eq!(values["force"], f64::INFINITY);
+
+    // found back beyond the latest checkpoints
+    db.transaction(|tx| {
+        for i in 7..250 {
+            State { data: i as f64 }.commit_checkpoint(tx)?;
+        }
+        Ok::<_, DbError>(())
+    })?;
+    assert_eq!(State::from_checkpoint_labeled(&db, "relaxed")?.data, 3.0);
+
+    Ok(())
+}
+
+#[test]
+fn test_checkpoint_retention() -> Result<()> {
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct State {
+        data: Vec<f64>,
+    }
+
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let chk = CheckpointDb::new(&tmpdb).retention(RetentionPolicy::KeepLast(3));
+
+    for i in 0..10 {
+        let x = State {
+            data: vec![i as f64; 1000],
+        };
+        if i == 0 {
+            chk.commit_with(&x, &CheckpointMeta::new().label("initial"))?;
+        } else {
+            chk.commit(&x)?;
+        }
+    }
+    let ckpts = chk.list::<State>()?;
+    assert_eq!(ckpts.iter().map(|x| x.seq).collect_vec(), [1, 8, 9, 10]);
+    assert_eq!(chk.load_from_latest::<State>()?.data[0], 9.0);
+    assert_eq!(chk.load_from_label::<State>("initial")?.data[0], 0.0);
+
+    // explicit pruning with another policy
+    let db = DbConnection::connect(&format!("{}", tmpdb.display()))?;
+    let size = std::fs::metadata(&tmpdb)?.len();
+    assert_eq!(State::prune_checkpoints(&db, RetentionPolicy::KeepLast(1))?, 2);
+    assert_eq!(State::checkpoints(&db)?.len(), 2);
+    db.vacuum()?;
+    assert!(std::fs::metadata(&tmpdb)?.len() < size);
+
+    Ok(())
+}
+
+#[test]
+fn test_checkpoint_group() -> Result<()> {
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Optimizer {
+        step: usize,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Positions {
+        data: Vec<f64>,
+    }
+
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let db = DbConnection::connect(&format!("{}", tmpdb.display()))?;
+
+    let group = CheckpointGroup::new("relax");
+    for i in 0..3 {
+        let info = group.commit(&db, |g| {
+            g.add(&Optimizer { step: i })?;
+            g.add(&Positions { data: vec![i as f64] })?;
+            Ok(())
+        })?;
+        assert_eq!(info.slot, i);
+        // committed alone, not part of the group
+        Positions { data: vec![-1.0] }.commit_checkpoint(&db)?;
+    }
+
+    // failed group is not committed
+    let e = group
+        .commit(&db, |g| {
+            g.add(&Optimizer { step: 99 })?;
+            Err(DbError::KeyNotFound { key: "test".into() })
+        })
+        .unwrap_err();
+    assert!(matches!(e, DbError::KeyNotFound { .. }));
+    assert_eq!(group.list(&db)?.len(), 3);
+    assert_eq!(Optimizer::checkpoints(&db)?.len(), 3);
+
+    let latest = group.load_n(&db, -1)?;
+    assert_eq!(latest.info().seq, 3);
+    assert_eq!(latest.get::<Optimizer>()?.step, 2);
+    assert_eq!(latest.get::<Positions>()?.data, [2.0]);
+    let first = group.load_n(&db, 0)?;
+    let mut x = Positions { data: vec![] };
+    first.restore(&mut x)?;
+    assert_eq!(x.data, [0.0]);
+    assert!(first.get::<Test>().unwrap_err().is_not_found());
+    assert!(group.load_n(&db, 3).unwrap_err().is_not_found());
+
+    // group members are kept on pruning
+    let chk = CheckpointDb::new(&tmpdb).retention(RetentionPolicy::KeepLast(2));
+    for _ in 0..3 {
+        chk.commit(&Positions { data: vec![-2.0] })?;
+    }
+    assert_eq!(Positions::checkpoints(&db)?.len(), 5);
+    let latest = group.load_n(&db, -1)?;
+    assert_eq!(latest.get::<Optimizer>()?.step, 2);
+    assert_eq!(latest.get::<Positions>()?.data, [2.0]);
+
+    // deleting a member deletes the whole group
+    let n = Positions::checkpoints(&db)?.len();
+    Optimizer::delete_checkpoint(&db, 0)?;
+    assert_eq!(group.list(&db)?.len(), 2);
+    assert_eq!(Positions::checkpoints(&db)?.len(), n - 1);
+    assert_eq!(group.load_n(&db, 0)?.get::<Positions>()?.data, [1.0]);
+
+    // groups are pruned as a whole
+    for i in 3..6 {
+        group.commit(&db, |g| {
+            let meta = if i == 3 {
+                CheckpointMeta::new().label("best")
+            } else {
+                CheckpointMeta::new()
+            };
+            g.add_with(&Optimizer { step: i }, &meta)?;
+            g.add(&Positions { data: vec![i as f64] })?;
+            Ok(())
+        })?;
+    }
+    assert_eq!(group.prune(&db, RetentionPolicy::KeepLast(2))?, 2);
+    assert_eq!(group.list(&db)?.iter().map(|x| x.seq).collect_vec(), [4, 5, 6]);
+    assert_eq!(Optimizer::checkpoints(&db)?.len(), 3);
+    assert_eq!(group.load_n(&db, 0)?.get::<Positions>()?.data, [3.0]);
+    assert_eq!(group.prune(&db, RetentionPolicy::KeepLast(1))?, 1);
+    assert_eq!(group.list(&db)?.iter().map(|x| x.seq).collect_vec(), [4, 6]);
+
+    Ok(())
+}
+
+#[test]
+fn test_delta_checkpoint() -> Result<()> {
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct State {
+        step: usize,
+        positions: Vec<f64>,
+    }
+
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let db = DbConnection::connect(&format!("{}", tmpdb.display()))?;
+    gosh_database::register::<State>().delta(4);
+
+    let mut x = State {
+        step: 0,
+        positions: (0..10000).map(|i| i as f64).collect(),
+    };
+    for i in 0..10 {
+        x.step = i;
+        x.positions[i * 100] = -1.0;
+        x.commit_checkpoint(&db)?;
+    }
+
+    let ckpts = State::checkpoints(&db)?;
+    for (i, ckpt) in ckpts.iter().enumerate() {
+        if i % 4 == 0 {
+            assert_eq!(ckpt.base_id, None);
+            assert!(ckpt.size > 80000);
+        } else {
+            assert_eq!(ckpt.base_id, Some(ckpts[i - 1].id));
+            assert!(ckpt.size < 100);
+        }
+    }
+    for i in 0..10 {
+        let x = State::from_checkpoint_n(&db, i)?;
+        assert_eq!(x.step, i as usize);
+        assert_eq!(x.positions[i as usize * 100], -1.0);
+        assert_eq!(x.positions[i as usize * 100 + 100], (i as f64 + 1.0) * 100.0);
+    }
+
+    // diffs remain valid after their base is deleted
+    assert_eq!(State::prune_checkpoints(&db, RetentionPolicy::KeepEvery(3))?, 6);
+    let ckpts = State::checkpoints(&db)?;
+    assert_eq!(ckpts.iter().map(|x| x.seq).collect_vec(), [3, 6, 9, 10]);
+    assert_eq!(ckpts[0].base_id, None);
+    assert!(ckpts[0].mtime > ckpts[0].ctime);
+    assert_eq!(ckpts[3].base_id, Some(ckpts[2].id));
+    assert_eq!(ckpts[3].mtime, ckpts[3].ctime);
+    for (i, ckpt) in ckpts.iter().enumerate() {
+        let x = State::from_checkpoint_n(&db, i as i32)?;
+        assert_eq!(x.step as i64, ckpt.seq - 1);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_checkpoint_writer() -> Result<()> {
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct State {
+        data: Vec<f64>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Bad {
+        data: std::collections::BTreeMap<Vec<i32>, f64>,
+    }
+
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let db = DbConnection::connect(&format!("{}", tmpdb.display()))?;
+
+    let writer = CheckpointWriter::with_capacity(&db, 2);
+    let mut x = State { data: vec![0.0; 100] };
+    for i in 0..20 {
+        x.data[i] = i as f64;
+        writer.commit(&x)?;
+    }
+    writer.flush()?;
+    let ckpts = State::checkpoints(&db)?;
+    assert_eq!(ckpts.len(), 20);
+    assert_eq!(State::from_checkpoint_n(&db, 5)?.data[5], 5.0);
+    assert_eq!(State::from_checkpoint_n(&db, 5)?.data[6], 0.0);
+
+    // errors are reported on the next call
+    gosh_database::register::<Bad>().codec::<gosh_database::codec::Json>();
+    let bad = Bad {
+        data: vec![(vec![1], 1.0)].into_iter().collect(),
+    };
+    writer.commit(&bad)?;
+    let e = writer.flush().unwrap_err();
+    assert!(matches!(e, DbError::Serialize { .. }));
+    writer.flush()?;
+
+    // pending commits are written on drop
+    let writer = CheckpointWriter::new(&db).retention(RetentionPolicy::KeepLast(3));
+    for _ in 0..5 {
+        writer.commit(&x)?;
+    }
+    drop(writer);
+    assert_eq!(State::checkpoints(&db)?.len(), 3);
+    // loading does not fork the history
+    assert_eq!(State::branches(&db)?.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_checkpoint_branch() -> Result<()> {
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct State {
+        step: usize,
+    }
+
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let url = format!("{}", tmpdb.display());
+    let db = DbConnection::connect(&url)?;
+
+    for i in 0..5 {
+        State { step: i }.commit_checkpoint(&db)?;
+    }
+    // continue from the latest in a new session
+    let db = DbConnection::connect(&url)?;
+    let mut x = State::from_checkpoint_n(&db, -1)?;
+    x.step += 1;
+    x.commit_checkpoint(&db)?;
+    assert_eq!(State::branches(&db)?.len(), 1);
+
+    // loading only does not fork
+    State::from_checkpoint_n(&db, 2)?;
+    assert_eq!(State::branches(&db)?.len(), 1);
+
+    // fork from slot 2
+    let mut x = State { step: 0 };
+    x.restore_from_checkpoint_n(&db, 2)?;
+    for _ in 0..3 {
+        x.step += 10;
+        x.commit_checkpoint(&db)?;
+    }
+    // fork from slot 2 again
+    x.restore_from_checkpoint_n(&db, 2)?;
+    State { step: 100 }.commit_checkpoint(&db)?;
+
+    let ckpts = State::checkpoints(&db)?;
+    let branches = State::branches(&db)?;
+    assert_eq!(branches.len(), 3);
+    assert_eq!(branches[0].len, 6);
+    assert_eq!(branches[0].tip.id, ckpts[5].id);
+    assert_eq!(branches[1].len, 3);
+    assert_eq!(branches[1].fork_id, Some(ckpts[2].id));
+    assert_eq!(branches[2].fork_id, Some(ckpts[2].id));
+    assert_eq!(State::from_checkpoint_branch(&db, 1)?.step, 32);
+    assert_eq!(State::from_checkpoint_branch(&db, 0)?.step, 5);
+
+    let ancestry = State::ancestry(&db, branches[1].tip.id)?;
+    assert_eq!(ancestry.iter().map(|x| x.slot).collect_vec(), [8, 7, 6, 2, 1, 0]);
+    assert!(State::ancestry(&db, -1).unwrap_err().is_not_found());
+
+    // ancestry is kept after deleting checkpoints in between
+    State::prune_checkpoints(&db, RetentionPolicy::KeepEvery(2))?;
+    let tip = State::branches(&db)?[1].tip.id;
+    let ancestry = State::ancestry(&db, tip)?;
+    assert_eq!(ancestry.iter().map(|x| x.seq).collect_vec(), [9, 8, 2]);
+
+    // the restored one is kept as parent when deleting it is rolled back
+    let mut x = State { step: 0 };
+    x.restore_from_checkpoint_n(&db, 0)?;
+    let restored = State::checkpoints(&db)?[0].id;
+    let r: Result<(), DbError> = db.transaction(|tx| {
+        State::delete_checkpoint(tx, 0)?;
+        Err(diesel::result::Error::RollbackTransaction.into())
+    });
+    assert!(r.is_err());
+    x.commit_checkpoint(&db)?;
+    assert_eq!(State::checkpoints(&db)?.last().unwrap().parent_id, Some(restored));
+
+    Ok(())
+}
+
+#[test]
+fn test_checkpoint_run() -> Result<()> {
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let path = format!("{}", tmpdb.display());
+
+    // two jobs sharing the same file
+    let chk_a = CheckpointDb::try_parse_from(["test", "--chk-file", &path, "--chk-run", "a"])?.create();
+    let chk_b = CheckpointDb::new(&path).run("b");
+    let meta = CheckpointMeta::new().tag("x").value("energy", -1.0);
+    for i in 0..3 {
+        chk_a.commit_with(&Test { data: i as f64 }, &meta)?;
+        chk_b.commit(&Test { data: -i as f64 })?;
+    }
+    assert_eq!(chk_a.load_from_slot_n::<Test>(1)?.data, 1.0);
+    assert_eq!(chk_b.load_from_slot_n::<Test>(1)?.data, -1.0);
+    let ckpts = chk_a.list::<Test>()?;
+    assert_eq!(ckpts.iter().map(|x| x.seq).collect_vec(), [1, 2, 3]);
+    assert_eq!(ckpts[0].run, "a");
+    // nothing in the default run
+    assert!(CheckpointDb::new(&path).list::<Test>()?.is_empty());
+
+    let db = DbConnection::connect(&path)?;
+    let group = CheckpointGroup::new("g");
+    group.commit(&db.with_run("a"), |g| g.add(&Test { data: 9.0 }))?;
+    assert_eq!(db.runs()?, ["a", "b"]);
+
+    // copy keeps history and metadata
+    assert_eq!(db.copy_run("a", "c")?, 4);
+    let e = db.copy_run("a", "b").unwrap_err();
+    assert!(matches!(e, DbError::RunExists { .. }));
+    let c = db.with_run("c");
+    let copied = Test::checkpoints(&c)?;
+    assert_eq!(copied.len(), 4);
+    assert_eq!(copied[1].meta, meta);
+    assert_eq!(copied[1].parent_id, Some(copied[0].id));
+    assert_eq!(Test::from_checkpoint_n(&c, 2)?.data, 2.0);
+    assert_eq!(group.load_n(&c, -1)?.get::<Test>()?.data, 9.0);
+
+    assert_eq!(db.delete_run("a")?, 4);
+    assert_eq!(db.runs()?, ["b", "c"]);
+    assert!(chk_a.list::<Test>()?.is_empty());
+    assert_eq!(Test::checkpoints(&c)?.len(), 4);
+
+    Ok(())
+}
+
+#[test]
+fn test_checkpoint_truncate() -> Result<()> {
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let chk = CheckpointDb::new(&tmpdb);
+    for i in 0..6 {
+        chk.commit(&Test { data: i as f64 })?;
+    }
+
+    // preview only
+    let deleted = chk.truncate_after::<Test>(2, true)?;
+    assert_eq!(deleted.iter().map(|x| x.slot).collect_vec(), [3, 4, 5]);
+    assert_eq!(chk.list::<Test>()?.len(), 6);
+    let ckpt = chk.delete_checkpoint::<Test>(-1, true)?.unwrap();
+    assert_eq!(ckpt.slot, 5);
+    assert_eq!(chk.list::<Test>()?.len(), 6);
+
+    // go back to slot 3 and resume from there
+    assert_eq!(chk.truncate_after::<Test>(-3, false)?.len(), 2);
+    assert_eq!(chk.load_from_latest::<Test>()?.data, 3.0);
+    let e = chk.truncate_after::<Test>(4, false).unwrap_err();
+    assert!(e.downcast_ref::<DbError>().is_some_and(|e| e.is_not_found()));
+
+    let ckpt = chk.delete_checkpoint::<Test>(1, false)?.unwrap();
+    assert_eq!(ckpt.seq, 2);
+    let ckpts = chk.list::<Test>()?;
+    assert_eq!(ckpts.iter().map(|x| x.seq).collect_vec(), [1, 3, 4]);
+    assert_eq!(ckpts[1].parent_id, Some(ckpts[0].id));
+    chk.commit(&Test { data: 6.0 })?;
+    assert_eq!(chk.load_from_slot_n::<Test>(3)?.data, 6.0);
+
+    // ids of deleted checkpoints can be reused by other keys
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Other {
+        data: f64,
+    }
+
+    let db = DbConnection::connect(&format!("{}", tdir.path().join("reuse.sqlite").display()))?;
+    for i in 0..3 {
+        Test { data: i as f64 }.commit_checkpoint(&db)?;
+    }
+    Test::truncate_after(&db, 0)?;
+    for i in 0..2 {
+        Other { data: i as f64 }.commit_checkpoint(&db)?;
+    }
+    Test { data: 3.0 }.commit_checkpoint(&db)?;
+    let ckpts = Test::checkpoints(&db)?;
+    assert_eq!(ckpts[1].parent_id, Some(ckpts[0].id));
+    assert_eq!(Test::ancestry(&db, ckpts[1].id)?.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_checkpoint_export() -> Result<()> {
+    let tdir = tempfile::tempdir()?;
+    let chk = CheckpointDb::new(tdir.path().join("a.sqlite"));
+    let meta = CheckpointMeta::new().label("good").step(10).value("energy", -1.0);
+    chk.commit(&Test { data: 0.0 })?;
+    chk.commit_with(&Test { data: 1.0 }, &meta)?;
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    let other = CheckpointDb::new(tdir.path().join("b.sqlite"));
+    other.commit(&Test { data: 2.0 })?;
+
+    let path = tdir.path().join("ckpt.bin");
+    let exported = chk.export_slot::<Test>(1, &path)?;
+    assert_eq!(exported.meta, meta);
+
+    // placed before the later one
+    let imported = other.import(&path)?;
+    assert_eq!(imported.slot, 0);
+    assert_eq!(imported.meta, meta);
+    assert_eq!(imported.ctime, exported.ctime);
+    assert_eq!(other.load_from_slot_n::<Test>(0)?.data, 1.0);
+    assert_eq!(other.load_from_label::<Test>("good")?.data, 1.0);
+    let ckpts = other.list::<Test>()?;
+    assert_eq!(ckpts.iter().map(|x| x.seq).collect_vec(), [1, 2]);
+    assert_eq!(ckpts.iter().map(|x| x.branch).collect_vec(), [1, 0]);
+
+    // into an empty file
+    let chk = CheckpointDb::new(tdir.path().join("c.sqlite"));
+    assert_eq!(chk.import(&path)?.branch, 0);
+    assert_eq!(chk.load_from_latest::<Test>()?.data, 1.0);
+
+    std::fs::write(&path, b"invalid")?;
+    assert!(chk.import(&path).is_err());
+    // header length of a corrupted file
+    std::fs::write(&path, [b"GOSHCKPT".as_slice(), &u32::MAX.to_le_bytes()].concat())?;
+    assert!(chk.import(&path).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_checkpoint_at() -> Result<()> {
+    let tdir = tempfile::tempdir()?;
+    let tmpdb = tdir.path().join("test.sqlite");
+    let path = format!("{}", tmpdb.display());
+    let db = DbConnection::connect(&path)?;
+
+    let now = || chrono::Utc::now().naive_utc();
+    let start = now();
+    let mut times = vec![];
+    for i in 0..3 {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        Test { data: i as f64 }.commit_checkpoint(&db)?;
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        times.push(now());
+    }
+    assert_eq!(Test::from_checkpoint_at(&db, times[0])?.data, 0.0);
+    assert_eq!(Test::from_checkpoint_at(&db, times[1])?.data, 1.0);
+    assert_eq!(Test::from_checkpoint_at(&db, now())?.data, 2.0);
+    let e = Test::from_checkpoint_at(&db, start).unwrap_err();
+    assert!(matches!(e, DbError::CheckpointNotFound { .. }));
+    assert!(e.to_string().contains("the first was created at"));
+
+    let time = times[1].format("%Y-%m-%dT%H:%M:%S%.fZ").to_string();
+    let chk = CheckpointDb::try_parse_from(["test", "--chk-file", &path, "--chk-time", &time])?.create();
+    let mut x = Test { data: -1.0 };
+    assert_eq!(chk.restore(&mut x)?.restored().map(|c| c.slot), Some(1));
+    assert_eq!(x.data, 1.0);
+    assert_eq!(chk.load_from_latest::<Test>()?.data, 1.0);
+    assert_eq!(chk.load_from_time::<Test>(times[0])?.data, 0.0);
+    // never start fresh silently for the requested time
+    let chk = chk.time(start);
+    assert!(chk.restore(&mut x).is_err());
+    assert_eq!(x.data, 1.0);
+    assert!(CheckpointDb::try_parse_from(["test", "--chk-time", "noon"]).is_err());
+    let args = ["test", "--chk-time", &time, "--chk-slot", "0"];
+    assert!(CheckpointDb::try_parse_from(args).is_err());
+
+    Ok(())
+}
 // tests:1 ends here